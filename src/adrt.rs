@@ -1,9 +1,31 @@
+pub mod aabb;
+pub mod adaptive;
+pub mod aperture;
+pub mod bvh;
 pub mod camera;
+pub mod checkpoint;
+pub mod curve;
+pub mod distribution;
+pub mod error;
+pub mod exposure;
+pub mod filter;
+pub mod hair;
 pub mod hittable;
 pub mod hittable_list;
+pub mod image;
+pub mod instance;
 pub mod interval;
+pub mod lens_system;
 pub mod material;
+pub mod progress;
+pub mod projection;
 pub mod ray;
+pub mod rng;
+pub mod sampler;
 pub mod sphere;
+pub mod stereo;
+pub mod stream;
+pub mod tile;
+pub mod transform;
 pub mod utility;
 pub mod vec3;
@@ -0,0 +1,229 @@
+use super::{interval::Interval, ray::Ray, utility::Point};
+
+/// Axis-aligned bounding box made up of one interval per axis.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+#[allow(dead_code)]
+impl Aabb {
+    pub const EMPTY: Aabb = Self {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+
+    pub fn new() -> Self {
+        Self::EMPTY
+    }
+
+    pub fn from(x: Interval, y: Interval, z: Interval) -> Self {
+        let mut aabb = Self { x, y, z };
+        aabb.pad_to_minimums();
+        aabb
+    }
+
+    /// Creates the box spanned by the two corner points `a` and `b`, which
+    /// don't need to be ordered.
+    pub fn from_points(a: Point, b: Point) -> Self {
+        Self::from(
+            Interval::from(a.x().min(b.x()), a.x().max(b.x())),
+            Interval::from(a.y().min(b.y()), a.y().max(b.y())),
+            Interval::from(a.z().min(b.z()), a.z().max(b.z())),
+        )
+    }
+
+    /// Creates the smallest box that encloses both `a` and `b`.
+    pub fn from_boxes(a: &Aabb, b: &Aabb) -> Self {
+        Self {
+            x: a.x.union(&b.x),
+            y: a.y.union(&b.y),
+            z: a.z.union(&b.z),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::from_boxes(self, other)
+    }
+
+    /// Returns the interval of the given axis, where 0 is x, 1 is y and 2 is z.
+    pub fn axis(&self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    pub fn centroid(&self) -> Point {
+        Point::from(
+            (self.x.min + self.x.max) * 0.5,
+            (self.y.min + self.y.max) * 0.5,
+            (self.z.min + self.z.max) * 0.5,
+        )
+    }
+
+    /// Returns the index of the axis with the largest extent.
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
+        }
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    /// Slab test of the ray against the box within `ray_t`.
+    pub fn hit(&self, ray: &Ray, ray_t: &Interval) -> bool {
+        let origin = [ray.origin().x(), ray.origin().y(), ray.origin().z()];
+        let direction = [
+            ray.direction().x(),
+            ray.direction().y(),
+            ray.direction().z(),
+        ];
+        let mut t_min = ray_t.min;
+        let mut t_max = ray_t.max;
+
+        for a in 0..3 {
+            let slab = self.axis(a);
+            let inv_d = 1.0 / direction[a];
+
+            let mut t0 = (slab.min - origin[a]) * inv_d;
+            let mut t1 = (slab.max - origin[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Adjusts the box so that no side is narrower than some delta, which
+    /// keeps flat objects from producing degenerate boxes.
+    fn pad_to_minimums(&mut self) {
+        let delta = 0.0001;
+        if self.x.size() < delta {
+            self.x = self.x.expand(delta);
+        }
+        if self.y.size() < delta {
+            self.y = self.y.expand(delta);
+        }
+        if self.z.size() < delta {
+            self.z = self.z.expand(delta);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adrt::vec3::Vec3;
+
+    fn unit_box() -> Aabb {
+        Aabb::from_points(Point::from(-1.0, -1.0, -1.0), Point::from(1.0, 1.0, 1.0))
+    }
+
+    fn hits(bbox: &Aabb, origin: Point, direction: Vec3) -> bool {
+        bbox.hit(
+            &Ray::from(origin, direction),
+            &Interval::from(0.001, f64::INFINITY),
+        )
+    }
+
+    #[test]
+    fn slab_test_hits_and_misses() {
+        let bbox = unit_box();
+        assert!(hits(
+            &bbox,
+            Point::from(0.0, 0.0, 5.0),
+            Vec3::from(0.0, 0.1, -1.0)
+        ));
+        assert!(hits(
+            &bbox,
+            Point::from(-3.0, -3.0, -3.0),
+            Vec3::from(1.0, 1.0, 1.0)
+        ));
+        assert!(!hits(
+            &bbox,
+            Point::from(0.0, 0.0, 5.0),
+            Vec3::from(1.0, 0.0, -1.0)
+        ));
+        // Pointing away from the box.
+        assert!(!hits(
+            &bbox,
+            Point::from(0.0, 0.0, 5.0),
+            Vec3::from(0.0, 0.0, 1.0)
+        ));
+        // The box lies behind the end of the ray interval.
+        assert!(!bbox.hit(
+            &Ray::from(Point::from(0.0, 0.0, 5.0), Vec3::from(0.0, 0.0, -1.0)),
+            &Interval::from(0.001, 3.0),
+        ));
+    }
+
+    #[test]
+    fn rays_parallel_to_an_axis() {
+        let bbox = unit_box();
+        // Direction components of zero only pass inside their slab.
+        assert!(hits(
+            &bbox,
+            Point::from(0.5, -0.5, 5.0),
+            Vec3::from(0.0, 0.0, -1.0)
+        ));
+        assert!(!hits(
+            &bbox,
+            Point::from(1.5, -0.5, 5.0),
+            Vec3::from(0.0, 0.0, -1.0)
+        ));
+        assert!(!hits(
+            &bbox,
+            Point::from(0.5, -1.5, 5.0),
+            Vec3::from(0.0, 0.0, -1.0)
+        ));
+        assert!(hits(
+            &bbox,
+            Point::from(-5.0, 0.9, 0.9),
+            Vec3::from(1.0, 0.0, 0.0)
+        ));
+        assert!(!hits(
+            &bbox,
+            Point::from(-5.0, 1.1, 0.9),
+            Vec3::from(1.0, 0.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn zero_thickness_boxes_are_padded() {
+        let flat = Aabb::from_points(Point::from(-1.0, -1.0, -2.0), Point::from(1.0, 1.0, -2.0));
+        assert!(flat.z.size() > 0.0);
+        assert!(flat.surface_area() > 0.0);
+        assert!(hits(&flat, Point::new(), Vec3::from(0.1, 0.2, -1.0)));
+        assert!(!hits(&flat, Point::new(), Vec3::from(1.0, 0.0, -1.0)));
+    }
+}
@@ -0,0 +1,145 @@
+use super::utility::Color;
+
+/// Fraction of the sample count below which the weight sum of a pixel is
+/// considered too small to divide by. Filter weights average one, so the
+/// weight sum normally stays close to the count.
+const MIN_WEIGHT_FRACTION: f64 = 0.25;
+
+/// Settings for adaptive sampling. Every pixel first takes `min_samples`
+/// samples and then keeps sampling until the relative standard error of
+/// its luminance drops below `threshold`, or it reaches `max_samples`.
+#[derive(Debug, Clone)]
+pub struct AdaptiveSampling {
+    pub min_samples: i32,
+    pub max_samples: i32,
+    /// Relative standard error of the pixel mean at which a pixel counts as
+    /// converged, e.g. 0.01 for 1%.
+    pub threshold: f64,
+    /// If set, a grayscale PGM image with the number of samples every pixel
+    /// received is written to this path, white being `max_samples`.
+    pub sample_count_file: Option<String>,
+}
+
+/// Running filter weighted sum and luminance variance of the samples of one
+/// pixel.
+///
+/// The variance is that of the unweighted samples, so the relative error
+/// that adaptive sampling and error targets use measures the noise of the
+/// radiance estimates, not the additional variance that the signs of the
+/// weights of filters with negative lobes add to the weighted average.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PixelEstimate {
+    pub sum: Color,
+    /// Sum of the samples without their filter weights.
+    pub sample_sum: Color,
+    pub weight_sum: f64,
+    pub count: i32,
+    pub mean: f64,
+    pub m2: f64,
+}
+
+#[allow(dead_code)]
+impl AdaptiveSampling {
+    pub fn from(min_samples: i32, max_samples: i32, threshold: f64) -> Self {
+        Self {
+            min_samples,
+            max_samples,
+            threshold,
+            sample_count_file: None,
+        }
+    }
+
+    /// Returns whether the pixel needs no further samples.
+    pub(crate) fn converged(&self, estimate: &PixelEstimate) -> bool {
+        estimate.count >= self.max_samples
+            || (estimate.count >= self.min_samples && estimate.relative_error() <= self.threshold)
+    }
+}
+
+/// Average relative error of all pixels, which measures how noisy the image
+/// is as a whole.
+pub(crate) fn mean_relative_error(pixels: &[PixelEstimate]) -> f64 {
+    if pixels.is_empty() {
+        return 0.0;
+    }
+    pixels.iter().map(|p| p.relative_error()).sum::<f64>() / pixels.len() as f64
+}
+
+impl PixelEstimate {
+    pub fn new() -> Self {
+        Self {
+            sum: Color::new(),
+            sample_sum: Color::new(),
+            weight_sum: 0.0,
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, sample: Color, weight: f64) {
+        self.sum += weight * sample;
+        self.sample_sum += sample;
+        self.weight_sum += weight;
+        self.count += 1;
+
+        // Welford's online algorithm for the variance.
+        let luminance = 0.2126 * sample.x() + 0.7152 * sample.y() + 0.0722 * sample.z();
+        let delta = luminance - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (luminance - self.mean);
+    }
+
+    /// Filter weighted average of the samples. Dividing by the sum of the
+    /// weights instead of the sample count keeps filters with negative lobes
+    /// from adding noise to flat areas.
+    ///
+    /// With few samples the negative weights of such filters can cancel the
+    /// positive ones, and dividing by the small sum would amplify the noise,
+    /// so the plain average of the samples is returned instead.
+    pub fn color(&self) -> Color {
+        if self.count == 0 {
+            return Color::new();
+        }
+        if self.weight_sum < MIN_WEIGHT_FRACTION * self.count as f64 {
+            return self.sample_sum / self.count as f64;
+        }
+        self.sum / self.weight_sum
+    }
+
+    /// Standard error of the mean luminance relative to the mean. Dark
+    /// pixels are measured against a small floor, so that they don't need
+    /// endless samples to converge.
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        f64::sqrt(variance / self.count as f64) / self.mean.max(0.01)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancelling_filter_weights_fall_back_to_the_sample_mean() {
+        let color = Color::from(0.2, 0.4, 0.6);
+        let mut estimate = PixelEstimate::new();
+        estimate.add(color, 1.3);
+        estimate.add(color, -1.3);
+        let result = estimate.color();
+        assert!((result.x() - 0.2).abs() < 1e-12);
+        assert!((result.y() - 0.4).abs() < 1e-12);
+        assert!((result.z() - 0.6).abs() < 1e-12);
+
+        // Once the positive weights dominate, the weighted average is used.
+        let mut estimate = PixelEstimate::new();
+        for _ in 0..3 {
+            estimate.add(Color::from(1.0, 1.0, 1.0), 1.3);
+        }
+        estimate.add(Color::from(0.5, 0.5, 0.5), -1.3);
+        assert!((estimate.color().x() - 1.25).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{f64::consts::PI, fs, sync::Arc};
+
+use super::{
+    distribution::Distribution2D, error::RenderError, image::read_header, vec3::sample_unit_disk,
+};
+
+/// Shape of the lens opening, which determines the shape of out-of-focus
+/// highlights (bokeh). Samples are returned in lens coordinates within
+/// `[-1, 1]^2`, which `Camera` scales by the defocus radius, and are mapped
+/// from a point of `[0, 1)^2` supplied by the sampler.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum Aperture {
+    Circle,
+    /// Regular polygon formed by `blades` diaphragm blades, rotated by
+    /// `rotation` degrees.
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    Mask(Arc<ApertureMask>),
+}
+
+/// Grayscale image of the aperture. Brighter pixels let more light through.
+#[derive(Debug)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    distribution: Distribution2D,
+}
+
+impl Aperture {
+    pub fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+        match self {
+            Aperture::Circle => {
+                let p = sample_unit_disk(u);
+                (p.x(), p.y())
+            }
+            Aperture::Polygon { blades, rotation } => {
+                Aperture::sample_polygon(*blades, *rotation, u)
+            }
+            Aperture::Mask(mask) => mask.sample(u),
+        }
+    }
+
+    fn sample_polygon(blades: u32, rotation: f64, (u0, u1): (f64, f64)) -> (f64, f64) {
+        let blades = blades.max(3);
+
+        // All triangles between the center and two neighbouring corners have
+        // the same area, so pick one uniformly and sample a point inside it,
+        // reusing the remainder of the first coordinate.
+        let scaled = u0 * blades as f64;
+        let sector = (scaled as u32).min(blades - 1);
+        let angle = |corner: u32| rotation.to_radians() + 2.0 * PI * corner as f64 / blades as f64;
+        let (a_sin, a_cos) = angle(sector).sin_cos();
+        let (b_sin, b_cos) = angle(sector + 1).sin_cos();
+
+        let mut u = (scaled - sector as f64).min(1.0);
+        let mut v = u1;
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+
+        (u * a_cos + v * b_cos, u * a_sin + v * b_sin)
+    }
+}
+
+#[allow(dead_code)]
+impl ApertureMask {
+    /// Creates a mask from `width * height` values stored row by row, top to
+    /// bottom. Fails if the mask lets no light through.
+    pub fn from(width: usize, height: usize, values: &[f64]) -> Result<Self, RenderError> {
+        Ok(Self {
+            width,
+            height,
+            distribution: Distribution2D::from(values, width, height)?,
+        })
+    }
+
+    /// Loads a mask from a binary (P5) or plain (P2) PGM image.
+    pub fn load(file_path: &str) -> Result<Self, RenderError> {
+        let data = fs::read(file_path).map_err(RenderError::io(file_path))?;
+        let invalid = |message: &str| RenderError::SceneLoad {
+            path: file_path.to_string(),
+            message: message.to_string(),
+        };
+
+        // The header consists of four whitespace separated tokens.
+        let (tokens, pos) = read_header(&data, 4);
+        if tokens.len() < 4 {
+            return Err(invalid("incomplete PGM header"));
+        }
+
+        let parse = |token: &str| {
+            token
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid PGM header"))
+        };
+        let width = parse(&tokens[1])?;
+        let height = parse(&tokens[2])?;
+        let max_value = parse(&tokens[3])?;
+        if width == 0 || height == 0 || max_value == 0 {
+            return Err(invalid("invalid PGM header"));
+        }
+
+        let values: Vec<f64> = match tokens[0].as_str() {
+            "P5" => {
+                // A single whitespace character separates header and data.
+                let bytes_per_value = if max_value > 255 { 2 } else { 1 };
+                let raster = data
+                    .get(pos + 1..pos + 1 + width * height * bytes_per_value)
+                    .ok_or_else(|| invalid("truncated PGM data"))?;
+                raster
+                    .chunks(bytes_per_value)
+                    .map(|c| {
+                        let value = if bytes_per_value == 2 {
+                            u16::from_be_bytes([c[0], c[1]]) as usize
+                        } else {
+                            c[0] as usize
+                        };
+                        value as f64 / max_value as f64
+                    })
+                    .collect()
+            }
+            "P2" => String::from_utf8_lossy(&data[pos..])
+                .split_whitespace()
+                .take(width * height)
+                .map(|token| parse(token).map(|value| value as f64 / max_value as f64))
+                .collect::<Result<Vec<f64>, RenderError>>()?,
+            _ => return Err(invalid("only P2 and P5 PGM images are supported")),
+        };
+        if values.len() != width * height {
+            return Err(invalid("truncated PGM data"));
+        }
+
+        Self::from(width, height, &values)
+            .map_err(|_| invalid("the mask is black and lets no light through"))
+    }
+
+    /// Samples a point proportionally to the mask brightness, so that
+    /// out-of-focus highlights take the shape of the mask.
+    fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+        let ((x, y), _) = self.distribution.sample(u);
+        // Fit the image into the lens square, keeping its aspect ratio, with
+        // the top row pointing up.
+        let aspect = self.width as f64 / self.height as f64;
+        let (sx, sy) = if aspect > 1.0 {
+            (1.0, 1.0 / aspect)
+        } else {
+            (aspect, 1.0)
+        };
+        ((2.0 * x - 1.0) * sx, (1.0 - 2.0 * y) * sy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Uniform samples on a grid that avoids the table boundaries.
+    fn samples() -> impl Iterator<Item = (f64, f64)> {
+        (0..51).flat_map(|i| {
+            (0..51).map(move |j| ((i as f64 + 0.37) / 51.0, (j as f64 + 0.61) / 51.0))
+        })
+    }
+
+    #[test]
+    fn polygon_samples_stay_inside_the_polygon() {
+        for (blades, rotation) in [(3, 0.0f64), (5, 18.0), (6, 30.0), (9, -7.5)] {
+            let corner = |k: u32| {
+                let angle = rotation.to_radians() + 2.0 * PI * k as f64 / blades as f64;
+                (angle.cos(), angle.sin())
+            };
+            let aperture = Aperture::Polygon { blades, rotation };
+            for u in samples() {
+                let (x, y) = aperture.sample(u);
+                for k in 0..blades {
+                    let (a, b) = (corner(k), corner(k + 1));
+                    let cross = (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0);
+                    assert!(cross >= -1e-12, "{:?} is outside a {}-gon", (x, y), blades);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mask_samples_avoid_masked_out_cells() {
+        let (width, height) = (4, 3);
+        #[rustfmt::skip]
+        let values = [
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.5,
+            0.2, 0.0, 0.0, 0.0,
+        ];
+        let mask = ApertureMask::from(width, height, &values).unwrap();
+        let aperture = Aperture::Mask(Arc::new(mask));
+
+        // The mask is fit into the lens square with its aspect ratio kept.
+        let (sx, sy) = (1.0, height as f64 / width as f64);
+        for u in samples() {
+            let (x, y) = aperture.sample(u);
+            let column = ((x / sx + 1.0) / 2.0 * width as f64) as usize;
+            let row = ((1.0 - y / sy) / 2.0 * height as f64) as usize;
+            assert!(
+                values[row * width + column] > 0.0,
+                "sampled cell {:?}",
+                (column, row)
+            );
+        }
+    }
+
+    #[test]
+    fn black_masks_are_rejected() {
+        assert!(ApertureMask::from(2, 2, &[0.0; 4]).is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("rust_tracer_{}_black.pgm", std::process::id()));
+        fs::write(&path, "P2\n2 2\n255\n0 0\n0 0\n").unwrap();
+        let result = ApertureMask::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(RenderError::SceneLoad { .. })));
+    }
+}
@@ -0,0 +1,501 @@
+use super::{
+    aabb::Aabb,
+    checkpoint::Fingerprint,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    ray::Ray,
+    utility::Point,
+};
+
+/// Number of buckets the centroids are binned into per axis when the
+/// surface area heuristic evaluates candidate splits.
+const BIN_COUNT: usize = 16;
+/// Leaves never hold more objects than this, even when splitting them is
+/// estimated to be more expensive.
+const MAX_LEAF_SIZE: usize = 8;
+/// Cost of visiting an interior node relative to intersecting one object.
+const TRAVERSAL_COST: f64 = 0.125;
+/// Depth below which nodes are split at the centroid median instead of with
+/// the surface area heuristic. Degenerate inputs, e.g. exponentially spaced
+/// objects, make the heuristic split off one object per level, and the
+/// median split bounds the remaining depth by the logarithm of the count.
+const MAX_SAH_DEPTH: usize = 32;
+/// Size of the traversal stack, which holds at most one node per level.
+const STACK_SIZE: usize = 64;
+
+/// Bounding volume hierarchy over `Hittable` objects.
+///
+/// The tree is built top-down with a binned surface area heuristic and then
+/// stored depth-first in a flat array: the first child of an interior node
+/// directly follows its parent, the node only stores the index of its second
+/// child. Traversal visits the child nearer to the ray origin first.
+pub struct Bvh<T: Hittable = Box<dyn Hittable>> {
+    objects: Vec<T>,
+    nodes: Vec<BvhNode>,
+    /// Position of every object in `objects`, indexed by the order in which
+    /// the objects were handed to the constructor.
+    positions: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bbox: Aabb,
+    /// Index of the first object for leaves, index of the second child for
+    /// interior nodes.
+    offset: usize,
+    /// Number of objects in a leaf, zero for interior nodes.
+    count: usize,
+    /// Axis the interior node was split along.
+    axis: usize,
+}
+
+struct BuildObject {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bbox: Aabb,
+    count: usize,
+}
+
+impl Bvh {
+    pub fn from(list: HittableList) -> Self {
+        Self::from_objects(list.objects)
+    }
+}
+
+#[allow(dead_code)]
+impl<T: Hittable> Bvh<T> {
+    pub fn from_objects(objects: Vec<T>) -> Self {
+        let mut build_objects: Vec<BuildObject> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let bbox = object.bounding_box();
+                BuildObject {
+                    index,
+                    bbox,
+                    centroid: bbox.centroid(),
+                }
+            })
+            .collect();
+
+        let mut nodes = Vec::with_capacity(2 * objects.len());
+        if !build_objects.is_empty() {
+            Self::build(&mut nodes, &mut build_objects, 0, 0);
+        }
+
+        // Reorder the objects so that every leaf references a contiguous range.
+        let mut positions = vec![0; objects.len()];
+        let mut slots: Vec<Option<T>> = objects.into_iter().map(Some).collect();
+        let objects = build_objects
+            .iter()
+            .enumerate()
+            .map(|(position, o)| {
+                positions[o.index] = position;
+                slots[o.index].take().expect("object referenced twice")
+            })
+            .collect();
+
+        Self {
+            objects,
+            nodes,
+            positions,
+        }
+    }
+
+    /// Returns the object that was passed to the constructor at `index`.
+    pub fn object(&self, index: usize) -> &T {
+        &self.objects[self.positions[index]]
+    }
+
+    /// Gives mutable access to the object that was passed to the constructor
+    /// at `index`. Call `refit` after changes that affect its bounding box.
+    pub fn object_mut(&mut self, index: usize) -> &mut T {
+        &mut self.objects[self.positions[index]]
+    }
+
+    /// Recomputes all node bounds from the current object bounds while keeping
+    /// the tree topology. This is much cheaper than a rebuild when objects
+    /// only moved a little, e.g. instances in an animation, but the tree
+    /// quality degrades as the objects drift away from their original layout.
+    pub fn refit(&mut self) {
+        // Children are always stored after their parent, so a reverse sweep
+        // updates every child before the node that contains it.
+        for i in (0..self.nodes.len()).rev() {
+            let node = self.nodes[i];
+            self.nodes[i].bbox = if node.count > 0 {
+                self.objects[node.offset..node.offset + node.count]
+                    .iter()
+                    .fold(Aabb::EMPTY, |bbox, o| bbox.union(&o.bounding_box()))
+            } else {
+                self.nodes[i + 1].bbox.union(&self.nodes[node.offset].bbox)
+            };
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Builds the subtree over `objects` at the given depth and returns the
+    /// index of its root. `objects` is a window starting at `start` into the
+    /// full object array.
+    fn build(
+        nodes: &mut Vec<BvhNode>,
+        objects: &mut [BuildObject],
+        start: usize,
+        depth: usize,
+    ) -> usize {
+        let bbox = objects
+            .iter()
+            .fold(Aabb::EMPTY, |bbox, o| bbox.union(&o.bbox));
+        let centroid_bounds = objects.iter().fold(Aabb::EMPTY, |bbox, o| {
+            bbox.union(&Aabb::from_points(o.centroid, o.centroid))
+        });
+
+        let node_index = nodes.len();
+        nodes.push(BvhNode {
+            bbox,
+            offset: start,
+            count: objects.len(),
+            axis: 0,
+        });
+
+        if objects.len() == 1 {
+            return node_index;
+        }
+
+        let split = if depth < MAX_SAH_DEPTH {
+            Self::find_split(objects, &bbox, &centroid_bounds)
+        } else {
+            Self::median_split(objects, &centroid_bounds)
+        };
+        let Some((axis, mid)) = split else {
+            return node_index;
+        };
+
+        let (left, right) = objects.split_at_mut(mid);
+        Self::build(nodes, left, start, depth + 1);
+        let second_child = Self::build(nodes, right, start + mid, depth + 1);
+
+        nodes[node_index].offset = second_child;
+        nodes[node_index].count = 0;
+        nodes[node_index].axis = axis;
+
+        node_index
+    }
+
+    /// Partitions `objects` along the cheapest split found by binning the
+    /// centroids and returns the axis together with the partition point, or
+    /// `None` if the objects should stay in a leaf.
+    fn find_split(
+        objects: &mut [BuildObject],
+        bbox: &Aabb,
+        centroid_bounds: &Aabb,
+    ) -> Option<(usize, usize)> {
+        let count = objects.len();
+        let leaf_cost = count as f64;
+        let parent_area = bbox.surface_area();
+
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            let extent = centroid_bounds.axis(axis);
+            if extent.size() <= 0.0 {
+                continue;
+            }
+
+            let mut bins = [Bin {
+                bbox: Aabb::EMPTY,
+                count: 0,
+            }; BIN_COUNT];
+            for object in objects.iter() {
+                let b = bin_index(component(&object.centroid, axis), extent);
+                bins[b].count += 1;
+                bins[b].bbox = bins[b].bbox.union(&object.bbox);
+            }
+
+            // Sweep from the right to get the cost of every right partition,
+            // then from the left to combine it with the left partitions.
+            let mut right_area = [0.0; BIN_COUNT];
+            let mut right_count = [0; BIN_COUNT];
+            let mut right_box = Aabb::EMPTY;
+            let mut n = 0;
+            for b in (1..BIN_COUNT).rev() {
+                right_box = right_box.union(&bins[b].bbox);
+                n += bins[b].count;
+                right_area[b] = right_box.surface_area();
+                right_count[b] = n;
+            }
+
+            let mut left_box = Aabb::EMPTY;
+            let mut n = 0;
+            for b in 1..BIN_COUNT {
+                left_box = left_box.union(&bins[b - 1].bbox);
+                n += bins[b - 1].count;
+                if n == 0 || right_count[b] == 0 {
+                    continue;
+                }
+
+                let cost = TRAVERSAL_COST
+                    + (left_box.surface_area() * n as f64 + right_area[b] * right_count[b] as f64)
+                        / parent_area;
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, b));
+                }
+            }
+        }
+
+        let (cost, axis, split_bin) = match best {
+            Some(best) => best,
+            None if count > MAX_LEAF_SIZE => {
+                // All centroids coincide, so fall back to an even split to
+                // keep leaves small.
+                let axis = bbox.longest_axis();
+                return Some((axis, count / 2));
+            }
+            None => return None,
+        };
+
+        if cost >= leaf_cost && count <= MAX_LEAF_SIZE {
+            return None;
+        }
+
+        let extent = centroid_bounds.axis(axis);
+        let mut mid = 0;
+        for i in 0..count {
+            if bin_index(component(&objects[i].centroid, axis), extent) < split_bin {
+                objects.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        Some((axis, mid))
+    }
+
+    /// Partitions `objects` at the median centroid along the longest axis of
+    /// the centroid bounds, or returns `None` if they fit into a leaf.
+    fn median_split(objects: &mut [BuildObject], centroid_bounds: &Aabb) -> Option<(usize, usize)> {
+        if objects.len() <= MAX_LEAF_SIZE {
+            return None;
+        }
+
+        let axis = centroid_bounds.longest_axis();
+        let mid = objects.len() / 2;
+        objects.select_nth_unstable_by(mid, |a, b| {
+            component(&a.centroid, axis).total_cmp(&component(&b.centroid, axis))
+        });
+        Some((axis, mid))
+    }
+}
+
+impl<T: Hittable> Hittable for Bvh<T> {
+    fn hit(&self, ray: &Ray, ray_t: &Interval, record: &mut HitRecord) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let direction_is_negative = [
+            ray.direction().x() < 0.0,
+            ray.direction().y() < 0.0,
+            ray.direction().z() < 0.0,
+        ];
+
+        let mut temp_rec = HitRecord::new();
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_size = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            if node
+                .bbox
+                .hit(ray, &Interval::from(ray_t.min, closest_so_far))
+            {
+                if node.count > 0 {
+                    for object in &self.objects[node.offset..node.offset + node.count] {
+                        if object.hit(
+                            ray,
+                            &Interval::from(ray_t.min, closest_so_far),
+                            &mut temp_rec,
+                        ) {
+                            hit_anything = true;
+                            closest_so_far = temp_rec.t;
+                            *record = temp_rec;
+                        }
+                    }
+                } else if direction_is_negative[node.axis] {
+                    stack[stack_size] = current + 1;
+                    stack_size += 1;
+                    current = node.offset;
+                    continue;
+                } else {
+                    stack[stack_size] = node.offset;
+                    stack_size += 1;
+                    current += 1;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+
+        hit_anything
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: &Interval) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        // Any intersection terminates the query, so the order in which the
+        // children are visited doesn't matter.
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_size = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit(ray, ray_t) {
+                if node.count > 0 {
+                    if self.objects[node.offset..node.offset + node.count]
+                        .iter()
+                        .any(|object| object.occluded(ray, ray_t))
+                    {
+                        return true;
+                    }
+                } else {
+                    stack[stack_size] = node.offset;
+                    stack_size += 1;
+                    current += 1;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                return false;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |root| root.bbox)
+    }
+
+    fn fingerprint(&self, fingerprint: &mut Fingerprint) {
+        // The tree only depends on the objects, so hashing them in the order
+        // they were handed to the constructor is enough.
+        fingerprint.write_str("bvh");
+        fingerprint.write_u64(self.positions.len() as u64);
+        for position in self.positions.iter() {
+            self.objects[*position].fingerprint(fingerprint);
+        }
+    }
+}
+
+fn component(point: &Point, axis: usize) -> f64 {
+    match axis {
+        0 => point.x(),
+        1 => point.y(),
+        _ => point.z(),
+    }
+}
+
+fn bin_index(value: f64, extent: &Interval) -> usize {
+    let b = (BIN_COUNT as f64 * (value - extent.min) / extent.size()) as usize;
+    b.min(BIN_COUNT - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adrt::{
+        material::MaterialType,
+        rng::Pcg32,
+        sphere::Sphere,
+        utility::{random, random_range, Color},
+        vec3::Vec3,
+    };
+
+    fn random_rays(rng: &mut Pcg32, count: usize) -> Vec<Ray> {
+        (0..count)
+            .map(|_| {
+                let direction = Vec3::from(random(rng) - 0.5, random(rng) - 0.5, -1.0);
+                Ray::from(Point::new(), direction)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closest_hits_match_hittable_list() {
+        let mut rng = Pcg32::new(7, 0);
+        let material = MaterialType::LAMBERTIAN(Color::from(0.5, 0.5, 0.5));
+        let mut list = HittableList::new();
+        let mut bvh_list = HittableList::new();
+        for _ in 0..500 {
+            let center = Point::from(
+                random_range(&mut rng, -20.0, 20.0),
+                random_range(&mut rng, -20.0, 20.0),
+                random_range(&mut rng, -60.0, -20.0),
+            );
+            let radius = random_range(&mut rng, 0.2, 2.0);
+            list.add(Box::new(Sphere::from(center, radius, material)));
+            bvh_list.add(Box::new(Sphere::from(center, radius, material)));
+        }
+        let bvh = Bvh::from(bvh_list);
+
+        let ray_t = Interval::from(0.001, f64::INFINITY);
+        let mut hits = 0;
+        for ray in random_rays(&mut rng, 2000) {
+            let mut list_record = HitRecord::new();
+            let mut bvh_record = HitRecord::new();
+            let list_hit = list.hit(&ray, &ray_t, &mut list_record);
+            let bvh_hit = bvh.hit(&ray, &ray_t, &mut bvh_record);
+
+            assert_eq!(list_hit, bvh_hit);
+            assert_eq!(list.occluded(&ray, &ray_t), bvh.occluded(&ray, &ray_t));
+            if list_hit {
+                hits += 1;
+                assert_eq!(list_record.t, bvh_record.t);
+                assert_eq!(list_record.normal.x(), bvh_record.normal.x());
+                assert_eq!(list_record.normal.y(), bvh_record.normal.y());
+                assert_eq!(list_record.normal.z(), bvh_record.normal.z());
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn exponentially_spaced_objects_stay_within_the_stack() {
+        // The heuristic only splits off the few farthest spheres per level,
+        // which without the median fallback builds a tree hundreds of levels
+        // deep.
+        let material = MaterialType::LAMBERTIAN(Color::from(0.5, 0.5, 0.5));
+        let spheres: Vec<Sphere> = (0..1000)
+            .map(|k| Sphere::from(Point::from(2f64.powi(k), 0.0, -5.0), 0.5, material))
+            .collect();
+        let bvh = Bvh::from_objects(spheres);
+
+        let ray = Ray::from(Point::from(1.0, 0.0, 0.0), Vec3::from(0.0, 0.0, -1.0));
+        let mut record = HitRecord::new();
+        assert!(bvh.hit(&ray, &Interval::from(0.001, f64::INFINITY), &mut record));
+        assert_eq!(record.t, 4.5);
+        assert!(bvh.occluded(&ray, &Interval::from(0.001, f64::INFINITY)));
+    }
+}
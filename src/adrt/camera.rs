@@ -1,37 +1,141 @@
 use std::{
     fs::File,
-    io::{BufWriter, Result, Write},
+    io::{BufWriter, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Instant,
 };
 
-use indicatif::{ProgressBar, ProgressStyle};
-
 use crate::adrt::{
-    hittable::HitRecord, interval::Interval, utility::write_color, vec3::unit_vector,
+    adaptive::{mean_relative_error, AdaptiveSampling, PixelEstimate},
+    aperture::Aperture,
+    checkpoint::{Checkpoint, CheckpointState, Fingerprint},
+    error::RenderError,
+    exposure::Exposure,
+    filter::{Filter, FilterSampler},
+    hittable::HitRecord,
+    image::Image,
+    interval::Interval,
+    lens_system::{FocusedLens, LensSystem},
+    progress::{CancellationToken, ProgressBarObserver, RenderObserver, RenderStats},
+    projection::Projection,
+    sampler::{Sampler, SamplerType},
+    stereo::{Eye, Stereo},
+    stream::StreamWriter,
+    tile::{CropWindow, Tile, TileOrder},
+    vec3::{cross, dot_product, unit_vector},
 };
 
 use super::{
     hittable::Hittable,
     ray::Ray,
-    utility::{random, Color, Point},
+    utility::{Color, Point},
     vec3::Vec3,
 };
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: i32,
     pub samples_per_pixel: i32,
     pub max_depth: i32,
+    /// Vertical field of view in degrees.
+    pub vfov: f64,
+    /// Point the camera is looking from.
+    pub lookfrom: Point,
+    /// Point the camera is looking at.
+    pub lookat: Point,
+    /// Camera-relative "up" direction.
+    pub vup: Vec3,
+    /// Variation angle of rays through each pixel in degrees, zero gives a
+    /// pinhole camera.
+    pub defocus_angle: f64,
+    /// Distance from `lookfrom` to the plane of perfect focus.
+    pub focus_dist: f64,
+    /// Shape of the lens opening used for depth of field.
+    pub aperture: Aperture,
+    /// Strength of the optical vignetting in `[0, 1]`. The lens barrel cuts
+    /// off part of the aperture towards the frame edges, which darkens them
+    /// and turns bokeh into cat-eye shapes. Zero disables it.
+    pub optical_vignetting: f64,
+    /// How film positions map to ray directions.
+    pub projection: Projection,
+    /// Renders both eyes of a stereo rig into one combined image.
+    pub stereo: Option<Stereo>,
+    /// Traces rays through a multi-element lens instead of using the
+    /// pinhole or thin lens model. The field of view follows from the lens
+    /// and film size, so `vfov`, `defocus_angle`, `aperture` and
+    /// `projection` are ignored. `focus_dist` sets the focus of the lens.
+    pub lens_system: Option<LensSystem>,
+    /// Scale from scene radiance to pixel values.
+    pub exposure: Exposure,
+    /// Exposure compensation in stops, applied on top of `exposure`.
+    pub exposure_compensation: f64,
+    /// Reconstruction filter the samples of a pixel are weighted with.
+    pub filter: Filter,
+    /// Number of render threads, zero uses all available cores.
+    pub thread_count: usize,
+    /// Side length of the square tiles the image is rendered in.
+    pub tile_size: i32,
+    /// Order in which the tiles are rendered.
+    pub tile_order: TileOrder,
+    /// Renders only part of the image. The returned image then covers just
+    /// the window, with its position as origin.
+    pub crop_window: Option<CropWindow>,
+    /// Sequence the pixel, lens and scattering samples are drawn from.
+    pub sampler: SamplerType,
+    /// Spends more samples on noisy pixels instead of taking
+    /// `samples_per_pixel` samples everywhere.
+    pub adaptive_sampling: Option<AdaptiveSampling>,
+    /// Number of samples every pixel gets per pass. The image is refined
+    /// pass by pass, and checkpoints are saved between passes.
+    pub samples_per_pass: i32,
+    /// Wall clock budget of the render in seconds. Passes are only started
+    /// if they are expected to finish in time, but the first pass always
+    /// runs. Setting this or `target_error` lifts the `samples_per_pixel`
+    /// limit, the render stops at whichever is reached first.
+    pub time_limit: Option<f64>,
+    /// Mean relative standard error of the pixels at which the image is
+    /// good enough, e.g. 0.02 for 2%. Checked after every pass.
+    pub target_error: Option<f64>,
+    /// Saves the accumulated samples periodically and optionally resumes
+    /// from them.
+    pub checkpoint: Option<Checkpoint>,
+    /// Seed for the random numbers. Every sample of every pixel is derived
+    /// from it, so the image only depends on the seed and not on the number
+    /// of threads, tile size or order.
+    pub seed: u64,
+    /// Receives the progress of the render, by default a progress bar in the
+    /// terminal.
+    pub observer: Arc<dyn RenderObserver>,
+    /// Cancels the render from another thread. Clone it before calling
+    /// `render`.
+    pub cancellation: CancellationToken,
     camera_config: CameraConfig,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 struct CameraConfig {
     image_height: i32,
+    // Width and height of the output image, which holds both eyes for stereo renders
+    output_size: (i32, i32),
+    // Part of the output image that is rendered
+    region: Tile,
     center: Point,
     pixel00_loc: Point,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
+    defocus_disk_u: Vec3,
+    defocus_disk_v: Vec3,
+    // Camera frame basis vectors
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    lens: Option<FocusedLens>,
+    filter: FilterSampler,
 }
 
 impl Camera {
@@ -41,94 +145,718 @@ impl Camera {
             image_width: 0,
             samples_per_pixel: 0,
             max_depth: 0,
+            vfov: 90.0,
+            lookfrom: Point::from(0.0, 0.0, 0.0),
+            lookat: Point::from(0.0, 0.0, -1.0),
+            vup: Vec3::from(0.0, 1.0, 0.0),
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+            aperture: Aperture::Circle,
+            optical_vignetting: 0.0,
+            projection: Projection::Perspective,
+            stereo: None,
+            lens_system: None,
+            exposure: Exposure::Unit,
+            exposure_compensation: 0.0,
+            filter: Filter::Box { radius: 0.5 },
+            thread_count: 0,
+            tile_size: 32,
+            tile_order: TileOrder::Scanline,
+            crop_window: None,
+            sampler: SamplerType::Independent,
+            adaptive_sampling: None,
+            samples_per_pass: 16,
+            time_limit: None,
+            target_error: None,
+            checkpoint: None,
+            seed: 0,
+            observer: Arc::new(ProgressBarObserver::new()),
+            cancellation: CancellationToken::new(),
             camera_config: CameraConfig::new(),
         }
     }
-    pub fn render(&mut self, file_path: &String, world: &impl Hittable) -> Result<()> {
-        let mut file = BufWriter::new(File::create(file_path)?);
-        let camera_config = self.initialize();
-        self.camera_config = camera_config;
 
-        let duration: u64 = (self.image_width * self.camera_config.image_height) as u64;
-        if duration == 0 {
-            panic!("Duration cannot be null, check the image size!");
+    /// Renders the world and returns the linear HDR image, which `Image::save`
+    /// or `Image::write` encode.
+    pub fn render(&mut self, world: &impl Hittable) -> Result<Image, RenderError> {
+        let start = Instant::now();
+        let region = self.prepare()?;
+        let pixel_count = (region.width * region.height) as usize;
+
+        let limited = self.time_limit.is_some() || self.target_error.is_some();
+        let max_samples = match &self.adaptive_sampling {
+            Some(adaptive) => adaptive.max_samples,
+            None if limited => i32::MAX,
+            None => self.samples_per_pixel,
+        };
+        let samples_per_pass = self.samples_per_pass.max(1) as i64;
+        let pass_count = ((max_samples.max(0) as i64 + samples_per_pass - 1) / samples_per_pass)
+            .min(u32::MAX as i64) as u32;
+
+        // Without a fixed number of passes the plan starts with one pass and is
+        // extended by the estimated number of remaining passes after each one.
+        let planned_passes = |finished_passes: u32| {
+            if limited {
+                (finished_passes + 1).min(pass_count)
+            } else {
+                pass_count
+            }
+        };
+        let exposure_scale = self.exposure.scale(self.exposure_compensation);
+        let thread_count = self.render_threads();
+
+        let tiles: Vec<Tile> = self
+            .tile_order
+            .tiles(region.width, region.height, self.tile_size)
+            .into_iter()
+            .map(|tile| Tile {
+                x: region.x + tile.x,
+                y: region.y + tile.y,
+                ..tile
+            })
+            .collect();
+
+        let mut scene_fingerprint = Fingerprint::new();
+        world.fingerprint(&mut scene_fingerprint);
+        let scene_hash = scene_fingerprint.finish();
+        let settings_hash = self.settings_hash();
+        let mut framebuffer = vec![PixelEstimate::new(); pixel_count];
+        let mut first_pass = 0;
+
+        if let Some(checkpoint) = self.checkpoint.as_ref().filter(|c| c.resume) {
+            if let Some(state) = checkpoint.load(
+                scene_hash,
+                settings_hash,
+                region.width as u32,
+                region.height as u32,
+            )? {
+                framebuffer = state.pixels;
+                first_pass = state.passes.min(pass_count);
+            }
         }
-        let sty = ProgressStyle::default_bar()
-            .template(
-                "[{elapsed_precise} | {percent}%] {bar:40.cyan/blue} {pos:>7}/{len:7} \n{msg}",
-            )
-            .expect("Could not create style for progress bar.");
 
-        let bar = ProgressBar::new(duration);
-        bar.set_style(sty);
+        let sample_count = |framebuffer: &[PixelEstimate]| -> u64 {
+            framebuffer.iter().map(|p| p.count as u64).sum()
+        };
+        let mut stats = RenderStats {
+            passes: first_pass,
+            planned_passes: planned_passes(first_pass),
+            samples: sample_count(&framebuffer),
+            mean_relative_error: mean_relative_error(&framebuffer),
+            elapsed: start.elapsed().as_secs_f64(),
+        };
+        self.observer
+            .render_started(region.width as usize, region.height as usize, &stats);
+        let mut last_checkpoint = Instant::now();
+        let mut cancelled = false;
 
-        file.write(
-            format!(
-                "P6\n{} {} 255\n",
-                self.image_width, self.camera_config.image_height
-            )
-            .as_bytes(),
-        )
-        .expect(format!("Cannot write to file '{}'", file_path).as_str());
-
-        for j in 0..self.camera_config.image_height {
-            for i in 0..self.image_width {
-                bar.inc(1);
-                let mut pixel_color = Color::from(0.0, 0.0, 0.0);
-                for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(i, j);
-                    pixel_color = pixel_color + self.ray_color(&ray, self.max_depth, world);
+        let mut pass = first_pass;
+        while pass < pass_count {
+            let pass_start = Instant::now();
+            let target_samples =
+                ((pass as i64 + 1) * samples_per_pass).min(max_samples as i64) as i32;
+            let samples_before = stats.samples;
+            let mut updates = Vec::with_capacity(tiles.len());
+
+            let camera = &*self;
+            let accumulated = &framebuffer;
+            let next_tile = AtomicUsize::new(0);
+            let (sender, receiver) = mpsc::channel();
+
+            thread::scope(|scope| {
+                for _ in 0..thread_count {
+                    let sender = sender.clone();
+                    let tiles = &tiles;
+                    let next_tile = &next_tile;
+                    scope.spawn(move || {
+                        while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed))
+                        {
+                            if camera.cancellation.is_cancelled() {
+                                break;
+                            }
+                            let pixels = camera.render_tile(
+                                tile,
+                                world,
+                                exposure_scale,
+                                Some(accumulated),
+                                target_samples,
+                            );
+                            camera.observer.tile_finished(pass, tile);
+                            if sender.send((*tile, pixels)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+                drop(sender);
+
+                updates.extend(receiver);
+            });
+
+            // The tiles finished before a cancellation are kept, the pass only counts once
+            // all of them are done.
+            let complete = updates.len() == tiles.len();
+            for (tile, pixels) in updates {
+                for (row, tile_row) in pixels.chunks(tile.width as usize).enumerate() {
+                    let start = ((tile.y - region.y + row as i32) * region.width + tile.x
+                        - region.x) as usize;
+                    framebuffer[start..start + tile_row.len()].copy_from_slice(tile_row);
+                }
+            }
+            if complete {
+                pass += 1;
+            }
+            cancelled = self.cancellation.is_cancelled();
+
+            stats.samples = sample_count(&framebuffer);
+            stats.mean_relative_error = mean_relative_error(&framebuffer);
+            stats.elapsed = start.elapsed().as_secs_f64();
+            let remaining = self.remaining_passes(
+                pass_count - pass,
+                pass,
+                stats.elapsed,
+                pass_start.elapsed().as_secs_f64(),
+                stats.mean_relative_error,
+            );
+            // A pass without new samples means every pixel has converged.
+            let finished = cancelled || remaining == 0 || stats.samples == samples_before;
+            stats.passes = pass;
+            stats.planned_passes = if limited {
+                pass + remaining
+            } else {
+                pass_count
+            };
+            if complete {
+                self.observer
+                    .pass_finished(&self.image(&framebuffer), &stats);
+            }
+
+            if let Some(checkpoint) = &self.checkpoint {
+                if finished || last_checkpoint.elapsed().as_secs_f64() >= checkpoint.interval {
+                    checkpoint.save(&CheckpointState {
+                        scene_hash,
+                        settings_hash,
+                        seed: self.seed,
+                        width: region.width as u32,
+                        height: region.height as u32,
+                        passes: pass,
+                        pixels: framebuffer.clone(),
+                    })?;
+                    last_checkpoint = Instant::now();
                 }
+            }
+            if finished {
+                break;
+            }
+        }
 
-                write_color(&mut file, pixel_color, self.samples_per_pixel).expect(
-                    format!("Cannot write {:?} to file '{}'", pixel_color, file_path).as_str(),
-                );
+        if let Some(adaptive) = &self.adaptive_sampling {
+            if let Some(count_path) = &adaptive.sample_count_file {
+                self.write_sample_counts(count_path, adaptive.max_samples, &framebuffer)
+                    .map_err(RenderError::io(count_path))?;
             }
         }
-        file.flush()
-            .expect(format!("Could not flush data to '{}'", file_path).as_str());
-        bar.finish_with_message("Rendering finished successfully!");
+        stats.elapsed = start.elapsed().as_secs_f64();
+        self.observer.render_finished(&stats, cancelled);
+
+        Ok(self.image(&framebuffer))
+    }
+
+    /// Renders the world straight into `writer`, for images too large to keep in memory.
+    /// Only the tiles being rendered are held in memory, plus one band of rows for writers
+    /// that take whole rows. Every pixel gets all of its samples at once, so time limits,
+    /// error targets and checkpoints are not available, and no sample count image is
+    /// written.
+    ///
+    /// A cancelled render returns `RenderError::Cancelled`. The file it leaves behind is
+    /// incomplete and can't be read as an image.
+    pub fn render_streamed(
+        &mut self,
+        world: &impl Hittable,
+        writer: &mut dyn StreamWriter,
+    ) -> Result<(), RenderError> {
+        let start = Instant::now();
+        if self.time_limit.is_some() || self.target_error.is_some() || self.checkpoint.is_some() {
+            return Err(RenderError::InvalidSettings(
+                "time limits, error targets and checkpoints are not available for streamed renders"
+                    .to_string(),
+            ));
+        }
+        let region = self.prepare()?;
+
+        let max_samples = match &self.adaptive_sampling {
+            Some(adaptive) => adaptive.max_samples,
+            None => self.samples_per_pixel,
+        };
+        let exposure_scale = self.exposure.scale(self.exposure_compensation);
+        let thread_count = self.render_threads();
+
+        // Writers of whole rows get one band of tiles at a time, tile writers every tile as
+        // soon as it is finished. Tiles are relative to the region, like the written image.
+        let row_writer = writer.tile_size().is_none();
+        let tile_size = writer
+            .tile_size()
+            .map_or(self.tile_size.max(1), |size| size as i32);
+        let band_height = if row_writer { tile_size } else { region.height };
+
+        writer.start(region.width as usize, region.height as usize)?;
+        let mut stats = RenderStats {
+            passes: 0,
+            planned_passes: 1,
+            samples: 0,
+            mean_relative_error: f64::INFINITY,
+            elapsed: start.elapsed().as_secs_f64(),
+        };
+        self.observer
+            .render_started(region.width as usize, region.height as usize, &stats);
+        let mut error_sum = 0.0;
+        let mut rendered_pixels = 0;
+        let mut cancelled = false;
+
+        for band_y in (0..region.height).step_by(band_height as usize) {
+            if self.cancellation.is_cancelled() {
+                cancelled = true;
+                break;
+            }
+
+            let band = Tile {
+                x: 0,
+                y: band_y,
+                width: region.width,
+                height: band_height.min(region.height - band_y),
+            };
+            let tiles: Vec<Tile> = self
+                .tile_order
+                .tiles(band.width, band.height, tile_size)
+                .into_iter()
+                .map(|tile| Tile {
+                    y: band.y + tile.y,
+                    ..tile
+                })
+                .collect();
+            let mut band_pixels = if row_writer {
+                vec![Color::new(); (band.width * band.height) as usize]
+            } else {
+                Vec::new()
+            };
+            let mut finished_tiles = 0;
+            let mut result = Ok(());
+
+            let camera = &*self;
+            let next_tile = AtomicUsize::new(0);
+            // A bounded channel keeps the render threads from getting ahead of the writer.
+            let (sender, receiver) = mpsc::sync_channel(thread_count);
+
+            thread::scope(|scope| {
+                for _ in 0..thread_count {
+                    let sender = sender.clone();
+                    let tiles = &tiles;
+                    let next_tile = &next_tile;
+                    scope.spawn(move || {
+                        while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed))
+                        {
+                            if camera.cancellation.is_cancelled() {
+                                break;
+                            }
+                            let image_tile = Tile {
+                                x: region.x + tile.x,
+                                y: region.y + tile.y,
+                                ..*tile
+                            };
+                            let pixels = camera.render_tile(
+                                &image_tile,
+                                world,
+                                exposure_scale,
+                                None,
+                                max_samples,
+                            );
+                            camera.observer.tile_finished(0, &image_tile);
+                            if sender.send((*tile, pixels)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+                drop(sender);
+
+                // Dropping the receiver on an error stops the render threads.
+                for (tile, estimates) in receiver {
+                    finished_tiles += 1;
+                    stats.samples += estimates.iter().map(|p| p.count as u64).sum::<u64>();
+                    error_sum += estimates.iter().map(|p| p.relative_error()).sum::<f64>();
+                    rendered_pixels += estimates.len();
+                    let pixels: Vec<Color> = estimates.iter().map(|p| p.color()).collect();
+
+                    if row_writer {
+                        for (row, tile_row) in pixels.chunks(tile.width as usize).enumerate() {
+                            let start =
+                                ((tile.y - band.y + row as i32) * band.width + tile.x) as usize;
+                            band_pixels[start..start + tile_row.len()].copy_from_slice(tile_row);
+                        }
+                    } else if let Err(error) = writer.write_block(&tile, &pixels) {
+                        result = Err(error);
+                        break;
+                    }
+                }
+            });
+            result?;
+
+            if finished_tiles < tiles.len() {
+                cancelled = true;
+                break;
+            }
+            if row_writer {
+                writer.write_block(&band, &band_pixels)?;
+            }
+        }
+
+        if !cancelled {
+            writer.finish()?;
+            stats.passes = 1;
+        }
+        if rendered_pixels > 0 {
+            stats.mean_relative_error = error_sum / rendered_pixels as f64;
+        }
+        stats.elapsed = start.elapsed().as_secs_f64();
+        self.observer.render_finished(&stats, cancelled);
+
+        if cancelled {
+            return Err(RenderError::Cancelled);
+        }
+        Ok(())
+    }
+
+    // Checks the settings and sets up the camera for rendering. Returns the part of the
+    // output image to render.
+    fn prepare(&mut self) -> Result<Tile, RenderError> {
+        self.validate()?;
+        self.camera_config = self.initialize()?;
+
+        let region = self.camera_config.region;
+        if region.width == 0 || region.height == 0 {
+            return Err(RenderError::InvalidSettings(format!(
+                "crop window {:?} lies outside of the image",
+                self.crop_window
+            )));
+        }
+        Ok(region)
+    }
+
+    fn render_threads(&self) -> usize {
+        if self.thread_count > 0 {
+            self.thread_count
+        } else {
+            thread::available_parallelism().map_or(1, |count| count.get())
+        }
+    }
+
+    // Converts the accumulated samples into an image.
+    fn image(&self, framebuffer: &[PixelEstimate]) -> Image {
+        let region = self.camera_config.region;
+        let mut image = Image::from(
+            region.width as usize,
+            region.height as usize,
+            framebuffer.iter().map(|pixel| pixel.color()).collect(),
+        );
+        image.set_origin(region.x as usize, region.y as usize);
+        let (output_width, output_height) = self.camera_config.output_size;
+        image.set_frame_size(output_width as usize, output_height as usize);
+        image
+    }
+
+    // Rejects settings that would give an empty image or a render that never ends.
+    fn validate(&self) -> Result<(), RenderError> {
+        let invalid = |message: String| Err(RenderError::InvalidSettings(message));
+
+        if self.image_width <= 0 {
+            return invalid(format!(
+                "image_width must be positive, got {}",
+                self.image_width
+            ));
+        }
+        if !self.aspect_ratio.is_finite() || self.aspect_ratio <= 0.0 {
+            return invalid(format!(
+                "aspect_ratio must be positive, got {}",
+                self.aspect_ratio
+            ));
+        }
+        match &self.adaptive_sampling {
+            Some(adaptive) if adaptive.max_samples <= 0 => {
+                return invalid(format!(
+                    "adaptive max_samples must be positive, got {}",
+                    adaptive.max_samples
+                ));
+            }
+            Some(adaptive) if adaptive.min_samples > adaptive.max_samples => {
+                return invalid(format!(
+                    "adaptive min_samples ({}) exceed max_samples ({})",
+                    adaptive.min_samples, adaptive.max_samples
+                ));
+            }
+            None if self.time_limit.is_none()
+                && self.target_error.is_none()
+                && self.samples_per_pixel <= 0 =>
+            {
+                return invalid(format!(
+                    "samples_per_pixel must be positive, got {}",
+                    self.samples_per_pixel
+                ));
+            }
+            _ => {}
+        }
+        if let Some(time_limit) = self.time_limit.filter(|t| t.is_nan() || *t <= 0.0) {
+            return invalid(format!("time_limit must be positive, got {}", time_limit));
+        }
+        if let Some(target_error) = self.target_error.filter(|e| e.is_nan() || *e <= 0.0) {
+            return invalid(format!(
+                "target_error must be positive, got {}",
+                target_error
+            ));
+        }
 
         Ok(())
     }
 
-    fn initialize(&self) -> CameraConfig {
+    // Returns how many more passes the render is expected to take, capped by the passes
+    // left to reach the maximum sample count. Zero ends the render.
+    fn remaining_passes(
+        &self,
+        pass_limit: u32,
+        passes: u32,
+        elapsed: f64,
+        pass_time: f64,
+        mean_error: f64,
+    ) -> u32 {
+        let mut remaining = pass_limit;
+        if let Some(time_limit) = self.time_limit {
+            // Only start passes that are expected to finish within the limit.
+            remaining = remaining.min(((time_limit - elapsed) / pass_time).max(0.0) as u32);
+        }
+        if let Some(target_error) = self.target_error {
+            if mean_error <= target_error {
+                remaining = 0;
+            } else if mean_error.is_finite() {
+                // The error falls with the square root of the number of samples.
+                let needed = passes as f64 * (mean_error / target_error).powi(2) - passes as f64;
+                remaining = remaining.min(needed.ceil().max(1.0) as u32);
+            } else {
+                remaining = remaining.min(1);
+            }
+        }
+        remaining
+    }
+
+    // Returns the number of samples per pixel the sampler spreads its strata over. Renders
+    // limited by time or error have no sample count, so every pass is stratified on its own.
+    fn sampler_size(&self) -> u32 {
+        let limited = self.time_limit.is_some() || self.target_error.is_some();
+        let samples = match &self.adaptive_sampling {
+            Some(adaptive) => adaptive.max_samples,
+            None if limited => self.samples_per_pass,
+            None => self.samples_per_pixel,
+        };
+        samples.max(1) as u32
+    }
+
+    // Hashes all settings that change the samples, leaving out the ones that only change
+    // how the work is scheduled, including the pass size unless it sizes the sampler.
+    fn settings_hash(&self) -> u64 {
+        let mut fingerprint = Fingerprint::new();
+        let adaptive = self
+            .adaptive_sampling
+            .as_ref()
+            .map(|a| (a.min_samples, a.max_samples, a.threshold));
+        let settings = [
+            format!("{:?}", self.aspect_ratio),
+            format!("{:?}", self.image_width),
+            format!("{:?}", self.samples_per_pixel),
+            format!("{:?}", self.max_depth),
+            format!("{:?}", self.vfov),
+            format!("{:?}", self.lookfrom),
+            format!("{:?}", self.lookat),
+            format!("{:?}", self.vup),
+            format!("{:?}", self.defocus_angle),
+            format!("{:?}", self.focus_dist),
+            format!("{:?}", self.aperture),
+            format!("{:?}", self.optical_vignetting),
+            format!("{:?}", self.projection),
+            format!("{:?}", self.stereo),
+            format!("{:?}", self.lens_system),
+            format!("{:?}", self.exposure),
+            format!("{:?}", self.exposure_compensation),
+            format!("{:?}", self.filter),
+            format!("{:?}", self.crop_window),
+            format!("{:?}", self.sampler),
+            format!("{:?}", self.sampler_size()),
+            format!("{:?}", adaptive),
+            format!("{:?}", self.seed),
+        ];
+        for setting in settings.iter() {
+            fingerprint.write_str(setting);
+        }
+        fingerprint.finish()
+    }
+
+    fn initialize(&self) -> Result<CameraConfig, RenderError> {
         // Calculate the image height, and ensure that it's at least 1
         let mut image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
         image_height = if image_height < 1 { 1 } else { image_height };
-        let center = Point::from(0.0, 0.0, 0.0);
+        let center = self.lookfrom;
 
-        let focal_length = 1.0;
-        let viewport_height = 2.0;
+        // Determine viewport dimensions.
+        let theta = self.vfov.to_radians();
+        let h = f64::tan(theta / 2.0);
+        let viewport_height = 2.0 * h * self.focus_dist;
         let viewport_width = viewport_height * (self.image_width as f64 / image_height as f64);
-        let camera_center = Point::from(0.0, 0.0, 0.0);
 
-        // Calculate the vectors across the horizontal and down the vertical viewport edgese
-        let viewport_u = Vec3::from(viewport_width, 0.0, 0.0);
-        let viewport_v = Vec3::from(0.0, -viewport_height, 0.0);
+        // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
+        let w = unit_vector(self.lookfrom - self.lookat);
+        let u = unit_vector(cross(self.vup, w));
+        let v = cross(w, u);
+
+        // Calculate the vectors across the horizontal and down the vertical viewport edges
+        let viewport_u = viewport_width * u;
+        let viewport_v = viewport_height * -v;
 
         // Calculate the horizontal and vertical delta vectors from pixel to pixel.
         let pixel_delta_u = viewport_u / self.image_width as f64;
         let pixel_delta_v = viewport_v / image_height as f64;
 
         // Calculate the location of the upper left pixel.
-        let viewport_upper_left = camera_center
-            - Vec3::from(0.0, 0.0, focal_length)
-            - (viewport_u / 2.0)
-            - (viewport_v / 2.0);
+        let viewport_upper_left =
+            center - (self.focus_dist * w) - (viewport_u / 2.0) - (viewport_v / 2.0);
 
         let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
-        CameraConfig::from(
+        // Calculate the camera defocus disk basis vectors.
+        let defocus_radius = self.focus_dist * f64::tan((self.defocus_angle / 2.0).to_radians());
+        let defocus_disk_u = u * defocus_radius;
+        let defocus_disk_v = v * defocus_radius;
+
+        let (output_width, output_height) = match &self.stereo {
+            Some(stereo) => stereo.output_size(self.image_width, image_height),
+            None => (self.image_width, image_height),
+        };
+        let region = match &self.crop_window {
+            Some(crop_window) => crop_window.region(output_width, output_height),
+            None => Tile {
+                x: 0,
+                y: 0,
+                width: output_width,
+                height: output_height,
+            },
+        };
+
+        let lens = self
+            .lens_system
+            .as_ref()
+            .map(|lens_system| {
+                lens_system.focus(
+                    self.focus_dist,
+                    self.image_width as f64 / image_height as f64,
+                )
+            })
+            .transpose()?;
+
+        Ok(CameraConfig {
             image_height,
+            output_size: (output_width, output_height),
+            region,
             center,
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
-        )
+            defocus_disk_u,
+            defocus_disk_v,
+            u,
+            v,
+            w,
+            lens,
+            filter: self.filter.sampler(),
+        })
+    }
+
+    // Continues the pixels of a tile row by row from their accumulated estimates until
+    // every pixel has target_samples samples or has converged. Returns the updated
+    // estimates, whose sums are exposed, but not yet averaged.
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        world: &impl Hittable,
+        exposure_scale: f64,
+        accumulated: Option<&[PixelEstimate]>,
+        target_samples: i32,
+    ) -> Vec<PixelEstimate> {
+        let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+        let mut sampler = self.sampler.create(self.sampler_size(), self.seed);
+        let region = self.camera_config.region;
+
+        for j in tile.y..tile.y + tile.height {
+            for i in tile.x..tile.x + tile.width {
+                let mut estimate = accumulated.map_or(PixelEstimate::new(), |accumulated| {
+                    accumulated[((j - region.y) * region.width + i - region.x) as usize]
+                });
+                loop {
+                    // Without adaptive sampling the target already includes the limit of
+                    // samples_per_pixel.
+                    let done = self
+                        .adaptive_sampling
+                        .as_ref()
+                        .is_some_and(|adaptive| adaptive.converged(&estimate));
+                    if done || estimate.count >= target_samples {
+                        break;
+                    }
+
+                    sampler.start_pixel_sample((i, j), estimate.count as u32);
+                    let (offset, filter_weight) =
+                        self.camera_config.filter.sample(sampler.get_2d());
+                    let sample = match self.get_output_ray(i, j, offset, sampler.as_mut()) {
+                        Some((ray, weight)) => {
+                            weight * self.ray_color(&ray, self.max_depth, world, sampler.as_mut())
+                        }
+                        None => Color::new(),
+                    };
+                    estimate.add(exposure_scale * sample, filter_weight);
+                }
+                pixels.push(estimate);
+            }
+        }
+
+        pixels
+    }
+
+    // Writes the number of samples every pixel received as a grayscale PGM image, with
+    // max_samples as white.
+    fn write_sample_counts(
+        &self,
+        file_path: &String,
+        max_samples: i32,
+        framebuffer: &[PixelEstimate],
+    ) -> std::io::Result<()> {
+        let max_samples = max_samples.max(1);
+        let mut file = BufWriter::new(File::create(file_path)?);
+        file.write_all(
+            format!(
+                "P5\n{} {} 255\n",
+                self.camera_config.region.width, self.camera_config.region.height
+            )
+            .as_bytes(),
+        )?;
+        let values: Vec<u8> = framebuffer
+            .iter()
+            .map(|pixel| (255 * pixel.count.min(max_samples) as u64 / max_samples as u64) as u8)
+            .collect();
+        file.write_all(&values)?;
+        file.flush()
     }
 
-    fn ray_color(&self, ray: &Ray, max_depth: i32, world: &impl Hittable) -> Color {
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        max_depth: i32,
+        world: &impl Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
         let mut record = HitRecord::new();
 
         if max_depth == 0 {
@@ -140,9 +868,9 @@ impl Camera {
             let mut scattered = Ray::new();
             if record
                 .material
-                .scatter(ray, &record, &mut attenuation, &mut scattered)
+                .scatter(ray, &record, &mut attenuation, &mut scattered, sampler)
             {
-                return attenuation * self.ray_color(&scattered, max_depth - 1, world);
+                return attenuation * self.ray_color(&scattered, max_depth - 1, world, sampler);
             }
 
             return Color::new();
@@ -156,23 +884,163 @@ impl Camera {
         (1.0 - a) * Color::from(1.0, 1.0, 1.0) + a * Color::from(0.5, 0.7, 1.0)
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let pixel_center = self.camera_config.pixel00_loc
-            + (i as f64 * self.camera_config.pixel_delta_u)
-            + (j as f64 * self.camera_config.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+    // Get a randomly-sampled camera ray through the given offset from the center of the
+    // pixel at location i,j of the output image, which holds both eyes for stereo renders,
+    // together with the weight of its radiance.
+    fn get_output_ray(
+        &self,
+        i: i32,
+        j: i32,
+        offset: (f64, f64),
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, f64)> {
+        match &self.stereo {
+            None => self.get_ray(i, j, offset, sampler),
+            Some(stereo) => {
+                let (eye, i, j) =
+                    stereo.eye_pixel(i, j, self.image_width, self.camera_config.image_height);
+                let (ray, weight) = self.get_ray(i, j, offset, sampler)?;
+                Some((self.eye_ray(&ray, stereo, eye), weight))
+            }
+        }
+    }
+
+    // Moves the origin of a ray of the center camera to the given eye, aiming it at the point
+    // where the original ray crosses the convergence distance.
+    fn eye_ray(&self, ray: &Ray, stereo: &Stereo, eye: Eye) -> Ray {
+        let config = &self.camera_config;
+        let half_ipd = eye.sign() * stereo.interpupillary_distance / 2.0;
+        let direction = unit_vector(*ray.direction());
 
-        let ray_origin = self.camera_config.center;
-        let ray_direction = pixel_sample - ray_origin;
+        let (offset, convergence_point) = if stereo.omnidirectional {
+            // Offset perpendicular to the horizontal part of the direction,
+            // so that the eyes lie on a circle around the camera center.
+            let right = dot_product(&direction, &config.u);
+            let forward = -dot_product(&direction, &config.w);
+            let length = f64::sqrt(right * right + forward * forward);
+            let offset = if length > 0.0 {
+                half_ipd * ((forward / length) * config.u + (right / length) * config.w)
+            } else {
+                Vec3::new()
+            };
+            (
+                offset,
+                ray.origin() + stereo.convergence_distance * direction,
+            )
+        } else {
+            // Shift both eyes sideways and intersect the ray with the zero
+            // parallax plane, which gives off-axis frustums.
+            let depth = -dot_product(&direction, &config.w);
+            let distance = if depth > 0.0 {
+                stereo.convergence_distance / depth
+            } else {
+                stereo.convergence_distance
+            };
+            (half_ipd * config.u, ray.origin() + distance * direction)
+        };
+
+        let origin = ray.origin() + offset;
+        Ray::from(origin, convergence_point - origin)
+    }
 
-        Ray::from(ray_origin, ray_direction)
+    // Get a randomly-sampled camera ray through the offset px,py from the center of the
+    // pixel at location i,j, originating from the camera defocus disk, and the weight of
+    // its radiance. Returns `None` if the ray is blocked by the lens barrel or the pixel
+    // lies outside of the area covered by the projection.
+    fn get_ray(
+        &self,
+        i: i32,
+        j: i32,
+        (px, py): (f64, f64),
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, f64)> {
+        // The lens dimensions are always drawn, right after the pixel dimensions, so that
+        // the scattering decisions use the same dimensions for every camera.
+        let lens_sample = sampler.get_2d();
+        let config = &self.camera_config;
+
+        if let Some(lens) = &config.lens {
+            let (ray, weight) = lens.generate_ray(
+                (i as f64 + 0.5 + px) / self.image_width as f64,
+                (j as f64 + 0.5 + py) / config.image_height as f64,
+                lens_sample,
+            )?;
+            let to_world = |p: &Vec3| p.x() * config.u + p.y() * config.v - p.z() * config.w;
+            let ray_origin = config.center + to_world(ray.origin());
+            let ray_direction = to_world(ray.direction());
+
+            return Some((Ray::from(ray_origin, ray_direction), weight));
+        }
+
+        let ray_origin = if self.defocus_angle <= 0.0 {
+            config.center
+        } else {
+            self.defocus_disk_sample(i, j, lens_sample)?
+        };
+
+        match self.projection {
+            Projection::Perspective => {
+                let pixel_sample = config.pixel00_loc
+                    + ((i as f64 + px) * config.pixel_delta_u)
+                    + ((j as f64 + py) * config.pixel_delta_v);
+                let ray_direction = pixel_sample - ray_origin;
+
+                Some((Ray::from(ray_origin, ray_direction), 1.0))
+            }
+            Projection::Orthographic => {
+                // All rays start on the plane through the camera center and
+                // meet the pixel on the focus plane.
+                let pixel_sample = config.pixel00_loc
+                    + ((i as f64 + px) * config.pixel_delta_u)
+                    + ((j as f64 + py) * config.pixel_delta_v);
+                let ray_origin =
+                    pixel_sample + self.focus_dist * config.w + (ray_origin - config.center);
+                let ray_direction = pixel_sample - ray_origin;
+
+                Some((Ray::from(ray_origin, ray_direction), 1.0))
+            }
+            _ => {
+                let (right, up, forward) = self.projection.direction(
+                    (i as f64 + 0.5 + px) / self.image_width as f64,
+                    (j as f64 + 0.5 + py) / config.image_height as f64,
+                    self.image_width as f64 / config.image_height as f64,
+                    self.vfov,
+                )?;
+                let direction = right * config.u + up * config.v - forward * config.w;
+                let focus_point = config.center + self.focus_dist * direction;
+                let ray_direction = focus_point - ray_origin;
+
+                Some((Ray::from(ray_origin, ray_direction), 1.0))
+            }
+        }
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + random();
-        let py = -0.5 + random();
+    // Maps the lens sample u to a point on the camera aperture, or `None` if the optical vignetting
+    // of pixel i,j blocks it.
+    fn defocus_disk_sample(&self, i: i32, j: i32, u: (f64, f64)) -> Option<Point> {
+        let (px, py) = self.aperture.sample(u);
 
-        (px * self.camera_config.pixel_delta_u) + (py * self.camera_config.pixel_delta_v)
+        if self.optical_vignetting > 0.0 {
+            // The barrel opening is a unit circle that moves away from the
+            // aperture as the pixel moves towards the frame corners.
+            let half_width = self.image_width as f64 / 2.0;
+            let half_height = self.camera_config.image_height as f64 / 2.0;
+            let half_diagonal = f64::sqrt(half_width * half_width + half_height * half_height);
+            let offset_x =
+                2.0 * self.optical_vignetting * (i as f64 + 0.5 - half_width) / half_diagonal;
+            let offset_y =
+                2.0 * self.optical_vignetting * (half_height - j as f64 - 0.5) / half_diagonal;
+            let (dx, dy) = (px - offset_x, py - offset_y);
+            if dx * dx + dy * dy > 1.0 {
+                return None;
+            }
+        }
+
+        Some(
+            self.camera_config.center
+                + (px * self.camera_config.defocus_disk_u)
+                + (py * self.camera_config.defocus_disk_v),
+        )
     }
 }
 
@@ -180,26 +1048,345 @@ impl CameraConfig {
     fn new() -> Self {
         Self {
             image_height: 0,
+            output_size: (0, 0),
+            region: Tile {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
             center: Point::new(),
             pixel00_loc: Point::new(),
             pixel_delta_u: Vec3::new(),
             pixel_delta_v: Vec3::new(),
+            defocus_disk_u: Vec3::new(),
+            defocus_disk_v: Vec3::new(),
+            u: Vec3::new(),
+            v: Vec3::new(),
+            w: Vec3::new(),
+            lens: None,
+            filter: Filter::Box { radius: 0.5 }.sampler(),
         }
     }
+}
 
-    fn from(
-        image_height: i32,
-        center: Point,
-        pixel00_loc: Point,
-        pixel_delta_u: Vec3,
-        pixel_delta_v: Vec3,
-    ) -> Self {
-        Self {
-            image_height,
-            center,
-            pixel00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adrt::{
+        hittable_list::HittableList, material::MaterialType, progress::QuietObserver,
+        sampler::IndependentSampler, sphere::Sphere,
+    };
+    use std::sync::Mutex;
+
+    // Records the tiles and the end of a render.
+    #[derive(Default)]
+    struct RecordingObserver {
+        tiles: AtomicUsize,
+        finished: Mutex<Option<(RenderStats, bool)>>,
+    }
+
+    impl RenderObserver for RecordingObserver {
+        fn tile_finished(&self, _pass: u32, _tile: &Tile) {
+            self.tiles.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn render_finished(&self, stats: &RenderStats, cancelled: bool) {
+            *self.finished.lock().unwrap() = Some((*stats, cancelled));
+        }
+    }
+
+    // Collects the written blocks into an image.
+    struct MemoryWriter {
+        tile_size: Option<usize>,
+        image: Image,
+        finished: bool,
+    }
+
+    impl StreamWriter for MemoryWriter {
+        fn tile_size(&self) -> Option<usize> {
+            self.tile_size
+        }
+
+        fn start(&mut self, width: usize, height: usize) -> Result<(), RenderError> {
+            self.image = Image::new(width, height);
+            Ok(())
+        }
+
+        fn write_block(&mut self, block: &Tile, pixels: &[Color]) -> Result<(), RenderError> {
+            let mut block_image =
+                Image::from(block.width as usize, block.height as usize, pixels.to_vec());
+            block_image.set_origin(block.x as usize, block.y as usize);
+            self.image.paste(&block_image)
         }
+
+        fn finish(&mut self) -> Result<(), RenderError> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    fn small_scene() -> HittableList {
+        HittableList::from(Box::new(Sphere::from(
+            Point::from(0.0, 0.0, -1.0),
+            0.5,
+            MaterialType::LAMBERTIAN(Color::from(0.7, 0.3, 0.3)),
+        )))
+    }
+
+    fn small_camera() -> Camera {
+        let mut camera = Camera::new();
+        camera.image_width = 37;
+        camera.aspect_ratio = 1.5;
+        camera.samples_per_pixel = 4;
+        camera.max_depth = 4;
+        camera.tile_size = 8;
+        camera.observer = Arc::new(QuietObserver);
+        camera.crop_window = Some(CropWindow::Pixels {
+            x: 3,
+            y: 5,
+            width: 29,
+            height: 17,
+        });
+        camera
+    }
+
+    fn same_pixels(a: &Image, b: &Image) -> bool {
+        a.width() == b.width()
+            && a.height() == b.height()
+            && a.pixels()
+                .iter()
+                .zip(b.pixels())
+                .all(|(a, b)| a.x() == b.x() && a.y() == b.y() && a.z() == b.z())
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_image() {
+        let world = small_scene();
+        let mut camera = small_camera();
+        camera.thread_count = 1;
+        let single = camera.render(&world).unwrap();
+        camera.thread_count = 8;
+        let parallel = camera.render(&world).unwrap();
+        assert!(same_pixels(&single, &parallel));
+    }
+
+    #[test]
+    fn seeds_not_tiles_determine_the_image() {
+        let world = small_scene();
+        let mut camera = small_camera();
+        camera.seed = 7;
+        let image = camera.render(&world).unwrap();
+        assert!(image.pixels().iter().any(|pixel| pixel.x() > 0.0));
+
+        for tile_size in [1, 5, 64] {
+            for tile_order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+                camera.tile_size = tile_size;
+                camera.tile_order = tile_order;
+                assert!(same_pixels(&image, &camera.render(&world).unwrap()));
+            }
+        }
+
+        camera.seed = 8;
+        assert!(!same_pixels(&image, &camera.render(&world).unwrap()));
+    }
+
+    #[test]
+    fn remaining_passes_stop_at_the_target_error_and_time_limit() {
+        let mut camera = Camera::new();
+        assert_eq!(camera.remaining_passes(20, 4, 1.0, 1.0, 0.5), 20);
+
+        camera.target_error = Some(0.01);
+        assert_eq!(camera.remaining_passes(20, 4, 1.0, 1.0, 0.01), 0);
+        assert_eq!(camera.remaining_passes(20, 4, 1.0, 1.0, 0.005), 0);
+        // Halving the error takes four times the samples.
+        assert_eq!(camera.remaining_passes(20, 4, 1.0, 1.0, 0.02), 12);
+        assert_eq!(camera.remaining_passes(5, 4, 1.0, 1.0, 0.02), 5);
+        assert_eq!(camera.remaining_passes(20, 4, 1.0, 1.0, 0.0101), 1);
+        assert_eq!(camera.remaining_passes(20, 0, 0.0, 1.0, f64::INFINITY), 1);
+
+        camera.target_error = None;
+        camera.time_limit = Some(10.0);
+        assert_eq!(camera.remaining_passes(20, 4, 4.0, 2.0, 0.5), 3);
+        assert_eq!(camera.remaining_passes(2, 4, 4.0, 2.0, 0.5), 2);
+        assert_eq!(camera.remaining_passes(20, 4, 9.0, 2.0, 0.5), 0);
+        assert_eq!(camera.remaining_passes(20, 4, 11.0, 2.0, 0.5), 0);
+
+        // Whichever limit is reached first stops the render.
+        camera.target_error = Some(0.01);
+        assert_eq!(camera.remaining_passes(20, 4, 4.0, 2.0, 0.02), 3);
+        assert_eq!(camera.remaining_passes(20, 4, 4.0, 2.0, 0.01), 0);
+    }
+
+    #[test]
+    fn limited_renders_size_the_sampler_by_the_pass() {
+        let mut camera = small_camera();
+        camera.sampler = SamplerType::Stratified;
+        assert_eq!(camera.sampler_size(), 4);
+        camera.time_limit = Some(60.0);
+        assert_eq!(camera.sampler_size(), 16);
+        camera.samples_per_pass = 9;
+        assert_eq!(camera.sampler_size(), 9);
+
+        // The first pass always runs, even if it uses up the time limit.
+        camera.time_limit = Some(1e-9);
+        let image = camera.render(&small_scene()).unwrap();
+        assert!(image.pixels().iter().any(|pixel| pixel.x() > 0.0));
+    }
+
+    #[test]
+    fn orthographic_rays_share_the_forward_direction() {
+        let mut camera = Camera::new();
+        camera.image_width = 64;
+        camera.aspect_ratio = 2.0;
+        camera.projection = Projection::Orthographic;
+        camera.lookfrom = Point::from(1.0, 2.0, 3.0);
+        camera.lookat = Point::from(1.0, 2.0, -7.0);
+        camera.vup = Vec3::from(0.0, 1.0, 0.0);
+        camera.camera_config = camera.initialize().unwrap();
+
+        let mut sampler = IndependentSampler::new(0);
+        let mut origins = Vec::new();
+        for (i, j) in [(0, 0), (63, 0), (0, 31), (63, 31), (32, 16)] {
+            sampler.start_pixel_sample((i, j), 0);
+            let (ray, weight) = camera
+                .get_ray(i, j, (0.25, -0.25), &mut sampler)
+                .expect("orthographic rays are never blocked");
+            let direction = unit_vector(*ray.direction());
+            assert_eq!(weight, 1.0);
+            assert!(direction.x().abs() < 1e-9 && direction.y().abs() < 1e-9);
+            assert!((direction.z() + 1.0).abs() < 1e-9);
+            origins.push(*ray.origin());
+        }
+        // The rays start at different points instead of the camera center.
+        assert!((origins[0].x() - origins[1].x()).abs() > 1e-3);
+        assert!((origins[0].y() - origins[2].y()).abs() > 1e-3);
+    }
+
+    #[test]
+    fn omnidirectional_eyes_are_offset_to_their_side() {
+        let mut camera = Camera::new();
+        camera.projection = Projection::Equirectangular;
+        camera.lookfrom = Point::new();
+        camera.lookat = Point::from(0.0, 0.0, -1.0);
+        camera.vup = Vec3::from(0.0, 1.0, 0.0);
+        camera.camera_config = camera.initialize().unwrap();
+        let stereo = Stereo::omnidirectional(0.064, 2.0);
+
+        // Viewing direction and the direction to the right of it.
+        let views = [
+            (Vec3::from(0.0, 0.0, -1.0), Vec3::from(1.0, 0.0, 0.0)),
+            (Vec3::from(1.0, 0.0, 0.0), Vec3::from(0.0, 0.0, 1.0)),
+            (Vec3::from(0.0, 0.0, 1.0), Vec3::from(-1.0, 0.0, 0.0)),
+            (Vec3::from(-1.0, 0.0, 0.0), Vec3::from(0.0, 0.0, -1.0)),
+        ];
+        for (direction, right) in views {
+            let ray = Ray::from(Point::new(), direction);
+            for eye in [Eye::Left, Eye::Right] {
+                let eye_ray = camera.eye_ray(&ray, &stereo, eye);
+                let expected = eye.sign() * 0.032 * right;
+                assert!((*eye_ray.origin() - expected).length() < 1e-12);
+                // Both eyes converge on the same point.
+                let target = *eye_ray.origin() + *eye_ray.direction();
+                assert!((target - 2.0 * direction).length() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn unfocusable_lens_systems_are_reported() {
+        let mut camera = small_camera();
+        camera.lens_system = Some(LensSystem::double_gauss_50mm());
+        camera.focus_dist = 0.1;
+        let result = camera.render(&small_scene());
+        assert!(matches!(result, Err(RenderError::InvalidSettings(_))));
+    }
+
+    #[test]
+    fn checkpoints_of_a_changed_scene_are_refused() {
+        let world = |x: f64| {
+            HittableList::from(Box::new(Sphere::from(
+                Point::from(x, 0.0, -1.0),
+                0.05,
+                MaterialType::LAMBERTIAN(Color::from(0.5, 0.5, 0.5)),
+            )))
+        };
+        let file_path = std::env::temp_dir().join(format!(
+            "rust_tracer_checkpoint_test_{}.ckpt",
+            std::process::id()
+        ));
+        let file_path = file_path.to_str().unwrap();
+
+        let mut camera = Camera::new();
+        camera.image_width = 8;
+        camera.aspect_ratio = 1.0;
+        camera.samples_per_pixel = 2;
+        camera.observer = Arc::new(QuietObserver);
+        camera.checkpoint = Some(Checkpoint::from(file_path, 0.0));
+        camera.render(&world(0.2)).unwrap();
+
+        camera.checkpoint = Some(Checkpoint {
+            resume: true,
+            ..Checkpoint::from(file_path, 0.0)
+        });
+        let unchanged = camera.render(&world(0.2));
+        let moved = camera.render(&world(0.25));
+        let _ = std::fs::remove_file(file_path);
+
+        assert!(unchanged.is_ok());
+        assert!(matches!(moved, Err(RenderError::Checkpoint { .. })));
+    }
+
+    #[test]
+    fn streamed_renders_match_in_memory_renders() {
+        let world = small_scene();
+        let mut camera = small_camera();
+        let image = camera.render(&world).unwrap();
+
+        for tile_size in [None, Some(16)] {
+            let mut writer = MemoryWriter {
+                tile_size,
+                image: Image::new(0, 0),
+                finished: false,
+            };
+            camera.render_streamed(&world, &mut writer).unwrap();
+            assert!(writer.finished);
+            assert_eq!(writer.image.width(), image.width());
+            assert_eq!(writer.image.height(), image.height());
+            for (streamed, rendered) in writer.image.pixels().iter().zip(image.pixels()) {
+                assert_eq!(streamed.x(), rendered.x());
+                assert_eq!(streamed.y(), rendered.y());
+                assert_eq!(streamed.z(), rendered.z());
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_renders_return_early() {
+        let observer = Arc::new(RecordingObserver::default());
+        let mut camera = small_camera();
+        camera.observer = observer.clone();
+        camera.cancellation.cancel();
+
+        let image = camera.render(&small_scene()).unwrap();
+        assert_eq!((image.width(), image.height()), (29, 17));
+        assert_eq!(observer.tiles.load(Ordering::Relaxed), 0);
+        let (stats, cancelled) = observer.finished.lock().unwrap().unwrap();
+        assert!(cancelled);
+        assert_eq!((stats.passes, stats.samples), (0, 0));
+    }
+
+    #[test]
+    fn cancelled_streamed_renders_fail() {
+        let mut camera = small_camera();
+        camera.cancellation.cancel();
+        let mut writer = MemoryWriter {
+            tile_size: None,
+            image: Image::new(0, 0),
+            finished: false,
+        };
+        let result = camera.render_streamed(&small_scene(), &mut writer);
+        assert!(matches!(result, Err(RenderError::Cancelled)));
+        assert!(!writer.finished);
     }
 }
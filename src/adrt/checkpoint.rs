@@ -0,0 +1,350 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, ErrorKind, Write},
+    sync::Arc,
+};
+
+use super::{
+    adaptive::PixelEstimate, error::RenderError, hittable::Hittable, utility::Color, vec3::Vec3,
+};
+
+/// Identifies checkpoint files and their layout version.
+const MAGIC: &[u8; 8] = b"RTCKPT04";
+/// Initial state of the FNV-1a hash.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+/// Size of the header: magic, scene and settings hash, seed, width, height
+/// and number of finished passes.
+const HEADER_SIZE: usize = 8 + 8 + 8 + 8 + 4 + 4 + 4;
+/// Size of one pixel: weighted and unweighted sum, weight sum, sample count,
+/// mean and M2 of the luminance.
+const PIXEL_SIZE: usize = 3 * 8 + 3 * 8 + 8 + 4 + 8 + 8;
+
+/// Periodically saved state of a progressive render, so that a render that
+/// got interrupted can continue where it stopped.
+///
+/// The file holds the accumulated sums and sample counts of every pixel.
+/// As the random numbers of a sample only depend on the seed, the pixel and
+/// the sample index, the seed and the counts are all the state the samplers
+/// need to continue.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub file_path: String,
+    /// Minimum time between two saves in seconds. Checkpoints are saved
+    /// between passes, so they can't be more frequent than the passes.
+    pub interval: f64,
+    /// Continue from the checkpoint file, if it exists. Checkpoints of a
+    /// different scene or with different camera settings are refused.
+    pub resume: bool,
+}
+
+/// Contents of a checkpoint file.
+pub(crate) struct CheckpointState {
+    pub scene_hash: u64,
+    pub settings_hash: u64,
+    pub seed: u64,
+    pub width: u32,
+    pub height: u32,
+    pub passes: u32,
+    pub pixels: Vec<PixelEstimate>,
+}
+
+/// FNV-1a hash, which unlike the standard library hasher is guaranteed to
+/// give the same value in every build, so checkpoints stay readable.
+///
+/// Objects shared between instances are hashed once and then represented by
+/// their hash, so a scene of many instances costs little more than one copy
+/// of its geometry.
+pub struct Fingerprint {
+    hash: u64,
+    shared: HashMap<usize, u64>,
+}
+
+#[allow(dead_code)]
+impl Checkpoint {
+    pub fn from(file_path: &str, interval: f64) -> Self {
+        Self {
+            file_path: file_path.to_string(),
+            interval,
+            resume: false,
+        }
+    }
+
+    /// Writes the state to a temporary file first and then replaces the
+    /// checkpoint with it, so a crash while saving keeps the previous one.
+    pub(crate) fn save(&self, state: &CheckpointState) -> Result<(), RenderError> {
+        let temp_path = format!("{}.tmp", self.file_path);
+        self.write(&temp_path, state)
+            .map_err(RenderError::io(&temp_path))?;
+
+        fs::rename(&temp_path, &self.file_path).map_err(RenderError::io(&self.file_path))
+    }
+
+    fn write(&self, file_path: &str, state: &CheckpointState) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(file_path)?);
+
+        file.write_all(MAGIC)?;
+        file.write_all(&state.scene_hash.to_le_bytes())?;
+        file.write_all(&state.settings_hash.to_le_bytes())?;
+        file.write_all(&state.seed.to_le_bytes())?;
+        file.write_all(&state.width.to_le_bytes())?;
+        file.write_all(&state.height.to_le_bytes())?;
+        file.write_all(&state.passes.to_le_bytes())?;
+        for pixel in state.pixels.iter() {
+            file.write_all(&pixel.sum.x().to_le_bytes())?;
+            file.write_all(&pixel.sum.y().to_le_bytes())?;
+            file.write_all(&pixel.sum.z().to_le_bytes())?;
+            file.write_all(&pixel.sample_sum.x().to_le_bytes())?;
+            file.write_all(&pixel.sample_sum.y().to_le_bytes())?;
+            file.write_all(&pixel.sample_sum.z().to_le_bytes())?;
+            file.write_all(&pixel.weight_sum.to_le_bytes())?;
+            file.write_all(&pixel.count.to_le_bytes())?;
+            file.write_all(&pixel.mean.to_le_bytes())?;
+            file.write_all(&pixel.m2.to_le_bytes())?;
+        }
+        file.flush()
+    }
+
+    /// Loads the checkpoint and checks that it belongs to the scene and the
+    /// settings being rendered. Returns `None` if there is no checkpoint
+    /// file yet.
+    pub(crate) fn load(
+        &self,
+        scene_hash: u64,
+        settings_hash: u64,
+        width: u32,
+        height: u32,
+    ) -> Result<Option<CheckpointState>, RenderError> {
+        let data = match fs::read(&self.file_path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(RenderError::io(&self.file_path)(error)),
+        };
+        let invalid = |message: &str| RenderError::Checkpoint {
+            path: self.file_path.clone(),
+            message: message.to_string(),
+        };
+
+        if data.len() < HEADER_SIZE || &data[0..8] != MAGIC {
+            return Err(invalid("is not a checkpoint file of this version"));
+        }
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        if u64_at(8) != scene_hash {
+            return Err(invalid("was saved for a different scene"));
+        }
+        if u64_at(16) != settings_hash {
+            return Err(invalid("was saved with different camera settings"));
+        }
+        let file_width = u32_at(32);
+        let file_height = u32_at(36);
+        if file_width != width || file_height != height {
+            return Err(invalid(&format!(
+                "has a resolution of {}x{} instead of {}x{}",
+                file_width, file_height, width, height
+            )));
+        }
+
+        // The pixel data must be exactly as long as the resolution requires before any of
+        // it is read.
+        let pixel_count = width as usize * height as usize;
+        let expected_len = pixel_count
+            .checked_mul(PIXEL_SIZE)
+            .and_then(|len| len.checked_add(HEADER_SIZE));
+        if expected_len != Some(data.len()) {
+            return Err(invalid("is truncated"));
+        }
+
+        let f64_at =
+            |offset: usize| f64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        let pixels = (0..pixel_count)
+            .map(|index| {
+                let offset = HEADER_SIZE + index * PIXEL_SIZE;
+                PixelEstimate {
+                    sum: Color::from(f64_at(offset), f64_at(offset + 8), f64_at(offset + 16)),
+                    sample_sum: Color::from(
+                        f64_at(offset + 24),
+                        f64_at(offset + 32),
+                        f64_at(offset + 40),
+                    ),
+                    weight_sum: f64_at(offset + 48),
+                    count: i32::from_le_bytes(data[offset + 56..offset + 60].try_into().unwrap()),
+                    mean: f64_at(offset + 60),
+                    m2: f64_at(offset + 68),
+                }
+            })
+            .collect();
+
+        Ok(Some(CheckpointState {
+            scene_hash,
+            settings_hash,
+            seed: u64_at(24),
+            width,
+            height,
+            passes: u32_at(40),
+            pixels,
+        }))
+    }
+}
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Self {
+            hash: FNV_OFFSET,
+            shared: HashMap::new(),
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+
+    pub fn write_vec3(&mut self, value: &Vec3) {
+        for component in [value.x(), value.y(), value.z()] {
+            self.write_f64(component);
+        }
+    }
+
+    /// Writes the fingerprint of an object that may be shared, computing it
+    /// only the first time the object is seen.
+    pub fn write_shared(&mut self, object: &Arc<dyn Hittable>) {
+        let key = Arc::as_ptr(object) as *const () as usize;
+        let hash = match self.shared.get(&key) {
+            Some(hash) => *hash,
+            None => {
+                let mut inner = Fingerprint {
+                    hash: FNV_OFFSET,
+                    shared: std::mem::take(&mut self.shared),
+                };
+                object.fingerprint(&mut inner);
+                self.shared = inner.shared;
+                self.shared.insert(key, inner.hash);
+                inner.hash
+            }
+        };
+        self.write_u64(hash);
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write(value.as_bytes());
+        // Separate consecutive strings, so that "ab" + "c" and "a" + "bc"
+        // differ.
+        self.write(&[0xff]);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adrt::{
+        bvh::Bvh, hittable_list::HittableList, instance::Instance, material::MaterialType,
+        sphere::Sphere, transform::Transform, utility::Point,
+    };
+
+    fn scene(small_x: f64, small_color: Color) -> Bvh {
+        let mut list = HittableList::new();
+        list.add(Box::new(Sphere::from(
+            Point::from(0.0, -100.5, -1.0),
+            100.0,
+            MaterialType::LAMBERTIAN(Color::from(0.8, 0.8, 0.0)),
+        )));
+        list.add(Box::new(Sphere::from(
+            Point::from(small_x, 0.0, -1.0),
+            0.01,
+            MaterialType::LAMBERTIAN(small_color),
+        )));
+        Bvh::from(list)
+    }
+
+    fn hash(world: &impl Hittable) -> u64 {
+        let mut fingerprint = Fingerprint::new();
+        world.fingerprint(&mut fingerprint);
+        fingerprint.finish()
+    }
+
+    #[test]
+    fn fingerprints_detect_small_scene_changes() {
+        let color = Color::from(0.1, 0.2, 0.3);
+        let original = hash(&scene(0.3, color));
+        assert_eq!(original, hash(&scene(0.3, color)));
+        assert_ne!(original, hash(&scene(0.3001, color)));
+        assert_ne!(original, hash(&scene(0.3, Color::from(0.1, 0.2, 0.4))));
+    }
+
+    #[test]
+    fn fingerprints_cover_shared_instance_objects() {
+        let instances = |radius: f64, offset: f64| {
+            let object: Arc<dyn Hittable> = Arc::new(Sphere::from(
+                Point::new(),
+                radius,
+                MaterialType::LAMBERTIAN(Color::from(0.5, 0.5, 0.5)),
+            ));
+            let mut list = HittableList::new();
+            for k in 0..3 {
+                let transform = Transform::translation(Vec3::from(k as f64 * offset, 0.0, -5.0));
+                list.add(Box::new(Instance::from(object.clone(), transform)));
+            }
+            hash(&list)
+        };
+
+        let original = instances(1.0, 3.0);
+        assert_eq!(original, instances(1.0, 3.0));
+        assert_ne!(original, instances(1.1, 3.0));
+        assert_ne!(original, instances(1.0, 3.5));
+    }
+
+    #[test]
+    fn truncated_and_oversized_checkpoints_are_refused() {
+        let file_path = std::env::temp_dir().join(format!(
+            "rust_tracer_{}_resolution.ckpt",
+            std::process::id()
+        ));
+        let checkpoint = Checkpoint::from(file_path.to_str().unwrap(), 0.0);
+        let state = CheckpointState {
+            scene_hash: 1,
+            settings_hash: 2,
+            seed: 3,
+            width: 2,
+            height: 2,
+            passes: 1,
+            pixels: vec![PixelEstimate::new(); 4],
+        };
+        checkpoint.save(&state).unwrap();
+        let loaded = checkpoint.load(1, 2, 2, 2).unwrap().unwrap();
+        assert_eq!(loaded.pixels.len(), 4);
+
+        // A resolution whose pixel count overflows 32 bits.
+        let mut data = fs::read(&file_path).unwrap();
+        data[32..36].copy_from_slice(&65_536u32.to_le_bytes());
+        data[36..40].copy_from_slice(&65_536u32.to_le_bytes());
+        fs::write(&file_path, &data).unwrap();
+        let oversized = checkpoint.load(1, 2, 65_536, 65_536);
+
+        checkpoint.save(&state).unwrap();
+        let data = fs::read(&file_path).unwrap();
+        fs::write(&file_path, &data[..data.len() - 1]).unwrap();
+        let truncated = checkpoint.load(1, 2, 2, 2);
+        fs::remove_file(&file_path).unwrap();
+
+        assert!(matches!(oversized, Err(RenderError::Checkpoint { .. })));
+        assert!(matches!(truncated, Err(RenderError::Checkpoint { .. })));
+    }
+}
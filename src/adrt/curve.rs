@@ -0,0 +1,440 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use super::{
+    aabb::Aabb,
+    checkpoint::Fingerprint,
+    error::RenderError,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    material::MaterialType,
+    ray::Ray,
+    utility::Point,
+    vec3::{cross, dot_product, unit_vector, Vec3},
+};
+
+/// A flat ribbon that follows a cubic Bezier segment and always faces the
+/// incoming ray. Its width is interpolated linearly from `width[0]` at the
+/// start to `width[1]` at the end, which makes it suited for hair, fur,
+/// grass blades and cables.
+pub struct Curve {
+    control_points: [Point; 4],
+    width: [f64; 2],
+    u_min: f64,
+    u_max: f64,
+    material: MaterialType,
+}
+
+#[allow(dead_code)]
+impl Curve {
+    pub fn from(control_points: [Point; 4], width: [f64; 2], material: MaterialType) -> Self {
+        Self::from_segment(control_points, width, 0.0, 1.0, material)
+    }
+
+    /// Creates a curve that covers `[u_min, u_max]` of a longer strand, so
+    /// `HitRecord::u` stays continuous across the segments of a strand.
+    pub fn from_segment(
+        control_points: [Point; 4],
+        width: [f64; 2],
+        u_min: f64,
+        u_max: f64,
+        material: MaterialType,
+    ) -> Self {
+        Self {
+            control_points,
+            width,
+            u_min,
+            u_max,
+            material,
+        }
+    }
+
+    fn recursive_intersect(
+        &self,
+        ray: &Ray,
+        ray_t: &Interval,
+        cp: &[Point; 4],
+        [u0, u1]: [f64; 2],
+        depth: i32,
+        record: &mut HitRecord,
+    ) -> bool {
+        let ray_length = ray.direction().length();
+        let z_max = ray_t.max * ray_length;
+
+        if depth > 0 {
+            let halves = subdivide_bezier(cp);
+            let u = [u0, (u0 + u1) / 2.0, u1];
+            let mut hit_anything = false;
+            let mut closest_so_far = *ray_t;
+
+            for (seg, half) in halves.iter().enumerate() {
+                // Skip halves whose bounds, widened by the curve width, miss
+                // the ray, which runs along +z through the origin.
+                let max_width =
+                    f64::max(lerp(u[seg], self.width), lerp(u[seg + 1], self.width)) * 0.5;
+                let (min, max) = bounds(half);
+                if max.x() + max_width < 0.0
+                    || min.x() - max_width > 0.0
+                    || max.y() + max_width < 0.0
+                    || min.y() - max_width > 0.0
+                    || max.z() + max_width < 0.0
+                    || min.z() - max_width > closest_so_far.max * ray_length
+                {
+                    continue;
+                }
+
+                if self.recursive_intersect(
+                    ray,
+                    &closest_so_far,
+                    half,
+                    [u[seg], u[seg + 1]],
+                    depth - 1,
+                    record,
+                ) {
+                    hit_anything = true;
+                    closest_so_far = Interval::from(ray_t.min, record.t);
+                }
+            }
+
+            return hit_anything;
+        }
+
+        // Test the ray against the segment endpoint regions.
+        let edge = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        if edge < 0.0 {
+            return false;
+        }
+        let edge = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if edge < 0.0 {
+            return false;
+        }
+
+        // Find the closest point on the segment to the ray in the xy plane.
+        let segment_direction = Vec3::from(cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y(), 0.0);
+        let denom = segment_direction.length_squared();
+        if denom == 0.0 {
+            return false;
+        }
+        let w = dot_product(&Vec3::from(-cp[0].x(), -cp[0].y(), 0.0), &segment_direction) / denom;
+        let u = (u0 + w * (u1 - u0)).clamp(u0, u1);
+        let hit_width = lerp(u, self.width);
+
+        let (pc, dpcdw) = evaluate_bezier(cp, w.clamp(0.0, 1.0));
+        let pt_curve_dist2 = pc.x() * pc.x() + pc.y() * pc.y();
+        if pt_curve_dist2 > hit_width * hit_width * 0.25 {
+            return false;
+        }
+        if pc.z() < 0.0 || pc.z() > z_max {
+            return false;
+        }
+
+        let t = pc.z() / ray_length;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        // `v` runs across the ribbon from 0 to 1, with 0.5 on the centerline.
+        let pt_curve_dist = f64::sqrt(pt_curve_dist2);
+        let edge_func = dpcdw.x() * -pc.y() + pc.x() * dpcdw.y();
+        let v = if edge_func > 0.0 {
+            0.5 + pt_curve_dist / hit_width
+        } else {
+            0.5 - pt_curve_dist / hit_width
+        };
+
+        record.t = t;
+        record.u = self.u_min + u * (self.u_max - self.u_min);
+        record.v = v;
+        record.pt = ray.at(t);
+        record.material = self.material;
+
+        let (_, tangent) = evaluate_bezier(&self.control_points, u);
+        let tangent = if tangent.near_zero() {
+            self.control_points[3] - self.control_points[0]
+        } else {
+            tangent
+        };
+        record.tangent = unit_vector(tangent);
+
+        // Shade the flat ribbon as if it were a cylinder, bending the normal
+        // around the tangent according to the offset from the centerline.
+        let across = unit_vector(cross(*ray.direction(), record.tangent));
+        let facing = unit_vector(cross(across, record.tangent));
+        let h = (2.0 * v - 1.0).clamp(-1.0, 1.0);
+        let outward_normal = f64::sqrt(1.0 - h * h) * facing - h * across;
+        record.set_face_normal(ray, &outward_normal);
+
+        true
+    }
+}
+
+impl Hittable for Curve {
+    fn hit(&self, ray: &Ray, ray_t: &Interval, record: &mut HitRecord) -> bool {
+        // Transform the control points into a coordinate system where the ray
+        // starts at the origin and points down +z.
+        let dz = unit_vector(*ray.direction());
+        let dx = if dz.x().abs() > dz.y().abs() {
+            Vec3::from(-dz.z(), 0.0, dz.x()) / f64::sqrt(dz.x() * dz.x() + dz.z() * dz.z())
+        } else {
+            Vec3::from(0.0, dz.z(), -dz.y()) / f64::sqrt(dz.y() * dz.y() + dz.z() * dz.z())
+        };
+        let dy = cross(dz, dx);
+
+        let cp = self.control_points.map(|p| {
+            let op = p - ray.origin();
+            Point::from(
+                dot_product(&op, &dx),
+                dot_product(&op, &dy),
+                dot_product(&op, &dz),
+            )
+        });
+
+        // Choose the subdivision depth so that the final segments are
+        // approximately linear.
+        let mut l0: f64 = 0.0;
+        for i in 0..2 {
+            l0 = l0
+                .max((cp[i].x() - 2.0 * cp[i + 1].x() + cp[i + 2].x()).abs())
+                .max((cp[i].y() - 2.0 * cp[i + 1].y() + cp[i + 2].y()).abs())
+                .max((cp[i].z() - 2.0 * cp[i + 1].z() + cp[i + 2].z()).abs());
+        }
+        let eps = f64::max(self.width[0], self.width[1]) * 0.05;
+        let r0 = (f64::log2(std::f64::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)) * 0.5).round();
+        let max_depth = if r0.is_nan() {
+            0
+        } else {
+            r0.clamp(0.0, 10.0) as i32
+        };
+
+        self.recursive_intersect(ray, ray_t, &cp, [0.0, 1.0], max_depth, record)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: &Interval) -> bool {
+        // Segments are short, so the closest hit search is as good as any.
+        self.hit(ray, ray_t, &mut HitRecord::new())
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The convex hull property of Bezier curves bounds the centerline,
+        // the width then extends it in every direction.
+        let (min, max) = bounds(&self.control_points);
+        let half_width = f64::max(self.width[0], self.width[1]) * 0.5;
+        let padding = Vec3::from(half_width, half_width, half_width);
+        Aabb::from_points(min - padding, max + padding)
+    }
+
+    fn fingerprint(&self, fingerprint: &mut Fingerprint) {
+        fingerprint.write_str("curve");
+        for point in self.control_points.iter() {
+            fingerprint.write_vec3(point);
+        }
+        for value in [self.width[0], self.width[1], self.u_min, self.u_max] {
+            fingerprint.write_f64(value);
+        }
+        fingerprint.write_str(&format!("{:?}", self.material));
+    }
+}
+
+/// Loads curves from a text strand file.
+///
+/// Every non-empty line that does not start with `#` describes one strand:
+/// the width at the root and at the tip, followed by the `3n + 1` control
+/// points of `n` joined cubic Bezier segments.
+///
+/// ```text
+/// # root_width tip_width x0 y0 z0 x1 y1 z1 x2 y2 z2 x3 y3 z3 ...
+/// 0.02 0.005 0 0 -1 0 0.1 -1 0.05 0.2 -1 0.1 0.3 -1
+/// ```
+#[allow(dead_code)]
+pub fn load_strands(file_path: &str, material: MaterialType) -> Result<HittableList, RenderError> {
+    let reader = BufReader::new(File::open(file_path).map_err(RenderError::io(file_path))?);
+    let mut curves = HittableList::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.map_err(RenderError::io(file_path))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |message: &str| RenderError::SceneLoad {
+            path: file_path.to_string(),
+            message: format!("line {}: {}", line_number + 1, message),
+        };
+
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|e| invalid(&e.to_string()))?;
+        if values.len() < 2 + 3 * 4 || !(values.len() - 2).is_multiple_of(3) {
+            return Err(invalid("expected two widths followed by 3D control points"));
+        }
+
+        let points: Vec<Point> = values[2..]
+            .chunks(3)
+            .map(|c| Point::from(c[0], c[1], c[2]))
+            .collect();
+        if !(points.len() - 1).is_multiple_of(3) {
+            return Err(invalid("expected 3n + 1 control points"));
+        }
+
+        let segments = (points.len() - 1) / 3;
+        for segment in 0..segments {
+            let u_min = segment as f64 / segments as f64;
+            let u_max = (segment + 1) as f64 / segments as f64;
+            let control_points = [
+                points[3 * segment],
+                points[3 * segment + 1],
+                points[3 * segment + 2],
+                points[3 * segment + 3],
+            ];
+            let width = [
+                values[0] + u_min * (values[1] - values[0]),
+                values[0] + u_max * (values[1] - values[0]),
+            ];
+            curves.add(Box::new(Curve::from_segment(
+                control_points,
+                width,
+                u_min,
+                u_max,
+                material,
+            )));
+        }
+    }
+
+    Ok(curves)
+}
+
+fn lerp(t: f64, values: [f64; 2]) -> f64 {
+    (1.0 - t) * values[0] + t * values[1]
+}
+
+fn blend(t: f64, a: Point, b: Point) -> Point {
+    (1.0 - t) * a + t * b
+}
+
+/// Splits a cubic Bezier segment at `t = 0.5` using de Casteljau's algorithm.
+fn subdivide_bezier(cp: &[Point; 4]) -> [[Point; 4]; 2] {
+    let p01 = blend(0.5, cp[0], cp[1]);
+    let p12 = blend(0.5, cp[1], cp[2]);
+    let p23 = blend(0.5, cp[2], cp[3]);
+    let p012 = blend(0.5, p01, p12);
+    let p123 = blend(0.5, p12, p23);
+    let mid = blend(0.5, p012, p123);
+
+    [[cp[0], p01, p012, mid], [mid, p123, p23, cp[3]]]
+}
+
+/// Returns the point and the derivative of a cubic Bezier segment at `t`.
+fn evaluate_bezier(cp: &[Point; 4], t: f64) -> (Point, Vec3) {
+    let cp1 = [
+        blend(t, cp[0], cp[1]),
+        blend(t, cp[1], cp[2]),
+        blend(t, cp[2], cp[3]),
+    ];
+    let cp2 = [blend(t, cp1[0], cp1[1]), blend(t, cp1[1], cp1[2])];
+
+    let derivative = if (cp2[1] - cp2[0]).length_squared() > 0.0 {
+        3.0 * (cp2[1] - cp2[0])
+    } else {
+        // The derivative vanishes at the endpoints when control points
+        // coincide, so fall back to the chord of the segment.
+        cp[3] - cp[0]
+    };
+
+    (blend(t, cp2[0], cp2[1]), derivative)
+}
+
+fn bounds(cp: &[Point; 4]) -> (Point, Point) {
+    let mut min = cp[0];
+    let mut max = cp[0];
+    for p in cp.iter().skip(1) {
+        min = Point::from(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z()));
+        max = Point::from(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z()));
+    }
+    (min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adrt::utility::Color;
+
+    fn material() -> MaterialType {
+        MaterialType::LAMBERTIAN(Color::from(0.5, 0.5, 0.5))
+    }
+
+    #[test]
+    fn straight_curves_are_hit_within_their_width() {
+        let curve = Curve::from(
+            [
+                Point::from(-1.0, 0.0, -2.0),
+                Point::from(-1.0 / 3.0, 0.0, -2.0),
+                Point::from(1.0 / 3.0, 0.0, -2.0),
+                Point::from(1.0, 0.0, -2.0),
+            ],
+            [0.1, 0.1],
+            material(),
+        );
+        let ray_t = Interval::from(0.001, f64::INFINITY);
+
+        let ray = Ray::from(Point::new(), Vec3::from(0.25, 0.0, -2.0));
+        let mut record = HitRecord::new();
+        assert!(curve.hit(&ray, &ray_t, &mut record));
+        assert!((record.pt.z() + 2.0).abs() < 1e-3);
+        assert!((record.pt.x() - 0.25).abs() < 1e-3);
+        assert!((record.u - 0.625).abs() < 1e-2);
+        assert!(curve.occluded(&ray, &ray_t));
+
+        // The ribbon is 0.1 wide, so it ends 0.05 away from the centerline.
+        let ray = Ray::from(Point::new(), Vec3::from(0.25, 0.06, -2.0));
+        let mut record = HitRecord::new();
+        assert!(!curve.hit(&ray, &ray_t, &mut record));
+        assert!(!curve.occluded(&ray, &ray_t));
+    }
+
+    fn write_strand_file(name: &str, contents: &str) -> String {
+        let file_path = std::env::temp_dir()
+            .join(format!("rust_tracer_{}_{}", std::process::id(), name))
+            .to_str()
+            .unwrap()
+            .to_string();
+        std::fs::write(&file_path, contents).unwrap();
+        file_path
+    }
+
+    #[test]
+    fn strands_load_into_one_curve_per_segment() {
+        let file_path = write_strand_file(
+            "strands.txt",
+            "# two strands\n\
+             0.02 0.01 0 0 -1 0 0.1 -1 0 0.2 -1 0 0.3 -1 0 0.4 -1 0 0.5 -1 0 0.6 -1\n\
+             \n\
+             0.03 0.03 1 0 -1 1 0.1 -1 1 0.2 -1 1 0.3 -1\n",
+        );
+        let curves = load_strands(&file_path, material());
+        let _ = std::fs::remove_file(&file_path);
+
+        let curves = curves.unwrap();
+        assert_eq!(curves.objects.len(), 3);
+        let bbox = curves.bounding_box();
+        assert!(bbox.x.min <= -0.01 && bbox.x.max >= 1.015);
+        assert!(bbox.y.min <= 0.0 && bbox.y.max >= 0.6);
+    }
+
+    #[test]
+    fn strands_need_3n_plus_1_control_points() {
+        let file_path = write_strand_file(
+            "bad_strands.txt",
+            "0.02 0.01 0 0 -1 0 0.1 -1 0 0.2 -1 0 0.3 -1 0 0.4 -1\n",
+        );
+        let curves = load_strands(&file_path, material());
+        let _ = std::fs::remove_file(&file_path);
+
+        assert!(matches!(curves, Err(RenderError::SceneLoad { .. })));
+    }
+}
@@ -0,0 +1,165 @@
+use super::error::RenderError;
+
+/// Piecewise-constant distribution over `[0, 1)` used to importance sample
+/// tabulated functions.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    function: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+/// Piecewise-constant distribution over `[0, 1)^2`, sampled by picking a row
+/// from the marginal distribution and then a column within that row.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+#[allow(dead_code)]
+impl Distribution1D {
+    /// Creates the distribution from non-negative function values, which
+    /// must not be empty or all zero.
+    pub fn from(function: &[f64]) -> Result<Self, RenderError> {
+        if function.is_empty() {
+            return Err(RenderError::InvalidSettings(
+                "cannot sample an empty function".to_string(),
+            ));
+        }
+        let distribution = Self::tabulate(function);
+        if distribution.integral == 0.0 {
+            return Err(RenderError::InvalidSettings(
+                "cannot sample a function that is zero everywhere".to_string(),
+            ));
+        }
+        Ok(distribution)
+    }
+
+    // Builds the distribution of a non-empty function, falling back to a
+    // uniform one if all values are zero. Rows of a 2D distribution may be
+    // zero as long as the whole function isn't.
+    fn tabulate(function: &[f64]) -> Self {
+        let n = function.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + function[i - 1].abs() / n as f64;
+        }
+
+        let integral = cdf[n];
+        if integral == 0.0 {
+            for (i, value) in cdf.iter_mut().enumerate() {
+                *value = i as f64 / n as f64;
+            }
+        } else {
+            for value in cdf.iter_mut() {
+                *value /= integral;
+            }
+        }
+
+        Self {
+            function: function.iter().map(|f| f.abs()).collect(),
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.function.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps the uniform sample `u` to a point in `[0, 1)` and returns it
+    /// together with its density and the index of the segment it fell into.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        // Find the last cdf entry that is less than or equal to `u`.
+        let offset = self
+            .cdf
+            .partition_point(|c| *c <= u)
+            .saturating_sub(1)
+            .min(self.count() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let pdf = if self.integral > 0.0 {
+            self.function[offset] / self.integral
+        } else {
+            1.0
+        };
+
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+}
+
+#[allow(dead_code)]
+impl Distribution2D {
+    /// Creates the distribution from `width * height` values stored row by
+    /// row, which must not all be zero.
+    pub fn from(function: &[f64], width: usize, height: usize) -> Result<Self, RenderError> {
+        if width == 0 || height == 0 || function.len() < width * height {
+            return Err(RenderError::InvalidSettings(format!(
+                "cannot sample {} values as a {}x{} table",
+                function.len(),
+                width,
+                height
+            )));
+        }
+
+        let conditional: Vec<Distribution1D> = function
+            .chunks(width)
+            .take(height)
+            .map(Distribution1D::tabulate)
+            .collect();
+        let row_integrals: Vec<f64> = conditional.iter().map(|d| d.integral()).collect();
+
+        Ok(Self {
+            conditional,
+            marginal: Distribution1D::from(&row_integrals)?,
+        })
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    /// Maps two uniform samples to a point in `[0, 1)^2` and returns it
+    /// together with its density.
+    pub fn sample(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample(u.1);
+        let (x, pdf_x, _) = self.conditional[row].sample(u.0);
+        ((x, y), pdf_x * pdf_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_and_zero_functions_are_rejected() {
+        assert!(Distribution1D::from(&[]).is_err());
+        assert!(Distribution1D::from(&[0.0, 0.0]).is_err());
+        assert!(Distribution2D::from(&[0.0; 6], 3, 2).is_err());
+        assert!(Distribution2D::from(&[1.0; 5], 3, 2).is_err());
+        assert!(Distribution2D::from(&[], 0, 0).is_err());
+    }
+
+    #[test]
+    fn zero_segments_are_never_sampled() {
+        let distribution = Distribution1D::from(&[0.0, 2.0, 0.0, 1.0]).unwrap();
+        assert_eq!(distribution.integral(), 0.75);
+        for i in 0..100 {
+            let (x, pdf, offset) = distribution.sample(i as f64 / 100.0);
+            assert!(offset == 1 || offset == 3);
+            assert_eq!((x * 4.0) as usize, offset);
+            assert!(pdf > 0.0);
+        }
+    }
+}
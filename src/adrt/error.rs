@@ -0,0 +1,68 @@
+use std::{error::Error, fmt, io};
+
+/// Errors of rendering an image and of loading the files a scene uses.
+#[derive(Debug)]
+pub enum RenderError {
+    /// A camera setting is out of range, e.g. a zero image width.
+    InvalidSettings(String),
+    /// The extension of `path` names no image format that can be written.
+    UnsupportedFormat { path: String },
+    /// Reading or writing the file at `path` failed.
+    Io { path: String, source: io::Error },
+    /// An input file, such as a lens prescription, an aperture mask or hair
+    /// strands, could not be parsed.
+    SceneLoad { path: String, message: String },
+    /// The image at `path`, e.g. one to composite a crop into, could not be
+    /// decoded.
+    ImageDecode { path: String, message: String },
+    /// An image does not fit into the image it is pasted into.
+    SizeMismatch(String),
+    /// The checkpoint at `path` is damaged or belongs to a different render.
+    Checkpoint { path: String, message: String },
+    /// A streamed render was cancelled before the image was complete.
+    Cancelled,
+}
+
+impl RenderError {
+    /// Attaches the path to an I/O error, for use with `map_err`.
+    pub(crate) fn io(path: &str) -> impl FnOnce(io::Error) -> Self {
+        let path = path.to_string();
+        move |source| RenderError::Io { path, source }
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::InvalidSettings(message) => {
+                write!(f, "invalid camera settings: {}", message)
+            }
+            RenderError::UnsupportedFormat { path } => {
+                write!(f, "'{}' has no supported image extension", path)
+            }
+            RenderError::Io { path, source } => write!(f, "'{}': {}", path, source),
+            RenderError::SceneLoad { path, message } => {
+                write!(f, "cannot load '{}': {}", path, message)
+            }
+            RenderError::ImageDecode { path, message } => {
+                write!(f, "cannot decode image '{}': {}", path, message)
+            }
+            RenderError::SizeMismatch(message) => write!(f, "image size mismatch: {}", message),
+            RenderError::Checkpoint { path, message } => {
+                write!(f, "checkpoint '{}' {}", path, message)
+            }
+            RenderError::Cancelled => {
+                write!(f, "the render was cancelled before the image was complete")
+            }
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RenderError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,78 @@
+/// Relates the exposure value to the luminance that saturates the sensor,
+/// from the saturation based speed of ISO 12232: 78 / (0.65 * 100).
+const SATURATION_RATIO: f64 = 1.2;
+
+/// How scene radiance is scaled to pixel values before output.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum Exposure {
+    /// Radiance is written as is, so a radiance of 1 gives a white pixel.
+    Unit,
+    /// Exposure of a real camera. Radiance is interpreted as luminance in
+    /// cd/m², so lights can be given in real-world units.
+    Manual {
+        /// Shutter time in seconds.
+        shutter_time: f64,
+        /// Ratio of the focal length to the aperture diameter.
+        f_number: f64,
+        /// Sensor sensitivity.
+        iso: f64,
+    },
+    /// Exposure value at ISO 100, as read off a light meter. Every step up
+    /// halves the brightness.
+    Ev100(f64),
+}
+
+impl Exposure {
+    /// Returns the exposure value at ISO 100, or `None` for `Exposure::Unit`.
+    pub fn ev100(&self) -> Option<f64> {
+        match *self {
+            Exposure::Unit => None,
+            Exposure::Manual {
+                shutter_time,
+                f_number,
+                iso,
+            } => Some(f64::log2(f_number * f_number / shutter_time * 100.0 / iso)),
+            Exposure::Ev100(ev100) => Some(ev100),
+        }
+    }
+
+    /// Returns the factor radiance is multiplied with, including an exposure
+    /// compensation of `compensation` stops.
+    pub fn scale(&self, compensation: f64) -> f64 {
+        let compensation = f64::powf(2.0, compensation);
+        match self.ev100() {
+            None => compensation,
+            Some(ev100) => {
+                // Luminance that just saturates the sensor maps to 1.
+                let max_luminance = SATURATION_RATIO * f64::powf(2.0, ev100);
+                compensation / max_luminance
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_exposure_value_halves_the_scale() {
+        assert!((Exposure::Ev100(0.0).scale(0.0) - 1.0 / 1.2).abs() < 1e-12);
+        for ev100 in -3..8 {
+            let scale = Exposure::Ev100(ev100 as f64).scale(0.0);
+            let next = Exposure::Ev100(ev100 as f64 + 1.0).scale(0.0);
+            assert!((next - scale / 2.0).abs() < 1e-12 * scale);
+        }
+
+        // f/1 at one second and ISO 100 is EV100 0, and compensation undoes a step.
+        let manual = Exposure::Manual {
+            shutter_time: 1.0,
+            f_number: 1.0,
+            iso: 100.0,
+        };
+        assert!((manual.scale(0.0) - 1.0 / 1.2).abs() < 1e-12);
+        assert!((Exposure::Ev100(1.0).scale(1.0) - 1.0 / 1.2).abs() < 1e-12);
+        assert_eq!(Exposure::Unit.scale(0.0), 1.0);
+    }
+}
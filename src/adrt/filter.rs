@@ -0,0 +1,184 @@
+use std::f64::consts::PI;
+
+use super::distribution::Distribution2D;
+
+/// Number of table cells per pixel along each axis, used to importance
+/// sample filters without an analytic inverse.
+const TABLE_DENSITY: f64 = 32.0;
+
+/// Reconstruction filter, which weights the samples of a pixel by their
+/// offset from the pixel center. The radius is given in pixels. Wide filters
+/// give smoother images, filters with negative lobes (Mitchell-Netravali and
+/// Lanczos) sharper ones.
+///
+/// Pixel samples are drawn proportionally to the filter instead of being
+/// splatted into neighbouring pixels, so a pixel only depends on its own
+/// samples and tiles can be rendered independently without seams.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+    /// Weights all samples equally. A radius of 0.5 covers exactly the pixel.
+    Box { radius: f64 },
+    /// Weight falls off linearly towards the radius.
+    Tent { radius: f64 },
+    /// Gaussian with standard deviation `sigma`, shifted down so that it
+    /// reaches zero at the radius. A radius of 1.5 and a `sigma` of 0.5 are
+    /// good defaults.
+    Gaussian { radius: f64, sigma: f64 },
+    /// Cubic filter by Mitchell and Netravali, who recommend `b = c = 1/3`
+    /// and a radius of 2.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    /// Sinc function windowed by a sinc stretched by `tau`, usually with a
+    /// radius and `tau` of 3.
+    Lanczos { radius: f64, tau: f64 },
+}
+
+/// Draws pixel offsets proportionally to the absolute value of a filter,
+/// using a tabulated version of it.
+#[derive(Debug, Clone)]
+pub(crate) struct FilterSampler {
+    radius: f64,
+    resolution: usize,
+    values: Vec<f64>,
+    distribution: Option<Distribution2D>,
+    weight: f64,
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => radius.max(0.0),
+        }
+    }
+
+    /// Filter value at the offset x,y from the pixel center. The values are
+    /// not normalized.
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        let radius = self.radius();
+        if x.abs() > radius || y.abs() > radius {
+            return 0.0;
+        }
+
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { .. } => (radius - x.abs()) * (radius - y.abs()),
+            Filter::Gaussian { sigma, .. } => {
+                let gaussian = |x: f64| f64::exp(-x * x / (2.0 * sigma * sigma));
+                let edge = gaussian(radius);
+                (gaussian(x) - edge).max(0.0) * (gaussian(y) - edge).max(0.0)
+            }
+            Filter::Mitchell { b, c, .. } => {
+                mitchell(2.0 * x / radius, b, c) * mitchell(2.0 * y / radius, b, c)
+            }
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau) * sinc(y) * sinc(y / tau),
+        }
+    }
+
+    pub(crate) fn sampler(&self) -> FilterSampler {
+        let radius = self.radius();
+        if let Filter::Box { .. } = self {
+            return FilterSampler::uniform(radius);
+        }
+
+        let resolution = ((2.0 * radius * TABLE_DENSITY).ceil() as usize).max(1);
+        let cell = 2.0 * radius / resolution as f64;
+        let values: Vec<f64> = (0..resolution * resolution)
+            .map(|index| {
+                let x = -radius + ((index % resolution) as f64 + 0.5) * cell;
+                let y = -radius + ((index / resolution) as f64 + 0.5) * cell;
+                self.evaluate(x, y)
+            })
+            .collect();
+        // A filter that is zero everywhere falls back to uniform sampling.
+        let Ok(distribution) = Distribution2D::from(&values, resolution, resolution) else {
+            return FilterSampler::uniform(radius);
+        };
+
+        // Samples are weighted with the sign of the filter times the ratio of the integrals of
+        // its absolute and its signed values, which makes the average weight one.
+        let integral = values.iter().sum::<f64>() / values.len() as f64;
+        let weight = if integral > 0.0 {
+            distribution.integral() / integral
+        } else {
+            1.0
+        };
+
+        FilterSampler {
+            radius,
+            resolution,
+            values,
+            distribution: Some(distribution),
+            weight,
+        }
+    }
+}
+
+impl FilterSampler {
+    // Samples the square of the filter radius uniformly, with unit weights.
+    fn uniform(radius: f64) -> Self {
+        Self {
+            radius,
+            resolution: 0,
+            values: Vec::new(),
+            distribution: None,
+            weight: 1.0,
+        }
+    }
+
+    /// Maps a sample in `[0, 1)^2` to an offset from the pixel center in
+    /// pixel units and returns it together with the weight of the sample.
+    pub fn sample(&self, (u0, u1): (f64, f64)) -> ((f64, f64), f64) {
+        let Some(distribution) = &self.distribution else {
+            return (
+                (
+                    self.radius * (2.0 * u0 - 1.0),
+                    self.radius * (2.0 * u1 - 1.0),
+                ),
+                1.0,
+            );
+        };
+
+        let ((x, y), _) = distribution.sample((u0, u1));
+        let column = ((x * self.resolution as f64) as usize).min(self.resolution - 1);
+        let row = ((y * self.resolution as f64) as usize).min(self.resolution - 1);
+        let weight = if self.values[row * self.resolution + column] < 0.0 {
+            -self.weight
+        } else {
+            self.weight
+        };
+
+        (
+            (self.radius * (2.0 * x - 1.0), self.radius * (2.0 * y - 1.0)),
+            weight,
+        )
+    }
+}
+
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x <= 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x <= 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    f64::sin(PI * x) / (PI * x)
+}
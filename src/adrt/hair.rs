@@ -0,0 +1,284 @@
+use std::f64::consts::PI;
+
+use super::{
+    hittable::HitRecord,
+    ray::Ray,
+    sampler::Sampler,
+    utility::Color,
+    vec3::{cross, dot_product, unit_vector},
+};
+
+/// Number of explicitly modelled scattering lobes: R, TT and TRT. All
+/// higher order paths are folded into one residual lobe.
+const P_MAX: usize = 3;
+
+/// A simplified version of the d'Eon/Chiang hair scattering model that is
+/// sampled lobe by lobe: reflection at the surface (R), transmission
+/// through the fiber (TT) and one internal reflection (TRT).
+///
+/// It expects `HitRecord::tangent` to point along the fiber and
+/// `HitRecord::v` to hold the offset across it, which is what `Curve`
+/// provides.
+#[derive(Debug, Clone, Copy)]
+pub struct Hair {
+    sigma_a: Color,
+    eta: f64,
+    v: [f64; P_MAX + 1],
+    s: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+#[allow(dead_code)]
+impl Hair {
+    /// Creates a hair material from its absorption coefficient, the
+    /// longitudinal and azimuthal roughness in `[0, 1]` and the tilt of the
+    /// cuticle scales in degrees.
+    pub fn from(sigma_a: Color, beta_m: f64, beta_n: f64, alpha: f64) -> Self {
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let s = f64::sqrt(PI / 8.0)
+            * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [f64::sin(alpha.to_radians()), 0.0, 0.0];
+        let mut cos_2k_alpha = [f64::sqrt(1.0 - sin_2k_alpha[0].powi(2)), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        Self {
+            sigma_a,
+            eta: 1.55,
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    /// Creates a hair material whose multiple scattering roughly reproduces
+    /// the given color.
+    pub fn from_color(color: Color, beta_m: f64, beta_n: f64, alpha: f64) -> Self {
+        let denominator = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let sigma_a = |c: f64| (f64::ln(c.max(1e-4)) / denominator).powi(2);
+
+        Self::from(
+            Color::from(sigma_a(color.x()), sigma_a(color.y()), sigma_a(color.z())),
+            beta_m,
+            beta_n,
+            alpha,
+        )
+    }
+
+    /// Creates a hair material from eumelanin and pheomelanin concentrations,
+    /// which covers the range of natural hair colors.
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64, beta_m: f64, beta_n: f64) -> Self {
+        let sigma_a = eumelanin * Color::from(0.419, 0.697, 1.37)
+            + pheomelanin * Color::from(0.187, 0.4, 1.05);
+        Self::from(sigma_a, beta_m, beta_n, 2.0)
+    }
+
+    pub fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        // Local frame: x along the fiber, y towards the outgoing direction
+        // projected onto the normal plane, so that phi_o is zero.
+        let wo = -unit_vector(*ray_in.direction());
+        let tangent = hit_record.tangent;
+        if tangent.near_zero() {
+            return false;
+        }
+        let sin_theta_o = dot_product(&wo, &tangent).clamp(-1.0, 1.0);
+        let cos_theta_o = f64::sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let perpendicular = wo - sin_theta_o * tangent;
+        let y_axis = if perpendicular.near_zero() {
+            hit_record.normal
+        } else {
+            unit_vector(perpendicular)
+        };
+        let z_axis = cross(tangent, y_axis);
+
+        let h = (2.0 * hit_record.v - 1.0).clamp(-1.0, 1.0);
+        let gamma_o = f64::asin(h);
+
+        // Refracted direction inside the fiber.
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = f64::sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let eta_p = f64::sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o;
+        let sin_gamma_t = h / eta_p;
+        let cos_gamma_t = f64::sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let gamma_t = f64::asin(sin_gamma_t);
+
+        let path_length = 2.0 * cos_gamma_t / cos_theta_t;
+        let transmittance = Color::from(
+            f64::exp(-self.sigma_a.x() * path_length),
+            f64::exp(-self.sigma_a.y() * path_length),
+            f64::exp(-self.sigma_a.z() * path_length),
+        );
+
+        let ap = attenuation_per_lobe(cos_theta_o * f64::cos(gamma_o), self.eta, transmittance);
+
+        // Pick a lobe proportionally to its average attenuation.
+        let weights = ap.map(|a| (a.x() + a.y() + a.z()) / 3.0);
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return false;
+        }
+        let mut u = sampler.get_1d() * total;
+        let mut p = P_MAX;
+        for (lobe, weight) in weights.iter().enumerate() {
+            if u < *weight {
+                p = lobe;
+                break;
+            }
+            u -= weight;
+        }
+        let pdf = weights[p] / total;
+
+        // Account for the tilt of the cuticle scales.
+        let (sin_theta_op, cos_theta_op) = match p {
+            0 => (
+                sin_theta_o * self.cos_2k_alpha[1] - cos_theta_o * self.sin_2k_alpha[1],
+                cos_theta_o * self.cos_2k_alpha[1] + sin_theta_o * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_theta_o * self.cos_2k_alpha[0] + cos_theta_o * self.sin_2k_alpha[0],
+                cos_theta_o * self.cos_2k_alpha[0] - sin_theta_o * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_theta_o * self.cos_2k_alpha[2] + cos_theta_o * self.sin_2k_alpha[2],
+                cos_theta_o * self.cos_2k_alpha[2] - sin_theta_o * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+
+        // Sample the longitudinal scattering function.
+        let v = self.v[p];
+        let (u0, u1) = sampler.get_2d();
+        let u0 = u0.max(1e-5);
+        let cos_theta = 1.0 + v * f64::ln(u0 + (1.0 - u0) * f64::exp(-2.0 / v));
+        let sin_theta = f64::sqrt((1.0 - cos_theta * cos_theta).max(0.0));
+        let cos_phi = f64::cos(2.0 * PI * u1);
+        let sin_theta_i =
+            (-cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op).clamp(-1.0, 1.0);
+        let cos_theta_i = f64::sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        // Sample the azimuthal scattering function.
+        let u2 = sampler.get_1d();
+        let dphi = if p < P_MAX {
+            let phi = 2.0 * p as f64 * gamma_t - 2.0 * gamma_o + p as f64 * PI;
+            phi + sample_trimmed_logistic(u2, self.s, -PI, PI)
+        } else {
+            2.0 * PI * u2
+        };
+
+        let direction = sin_theta_i * tangent
+            + cos_theta_i * f64::cos(dphi) * y_axis
+            + cos_theta_i * f64::sin(dphi) * z_axis;
+
+        *scattered = Ray::from(hit_record.pt, direction);
+        *attenuation = ap[p] / pdf;
+        true
+    }
+}
+
+fn attenuation_per_lobe(cos_theta: f64, eta: f64, transmittance: Color) -> [Color; P_MAX + 1] {
+    let f = fresnel_dielectric(cos_theta, eta);
+
+    let a0 = Color::from(f, f, f);
+    let a1 = (1.0 - f) * (1.0 - f) * transmittance;
+    let a2 = a1 * f * transmittance;
+    let residual = transmittance * f;
+    let a3 = a2
+        * Color::from(
+            residual.x() / (1.0 - residual.x()),
+            residual.y() / (1.0 - residual.y()),
+            residual.z() / (1.0 - residual.z()),
+        );
+
+    [a0, a1, a2, a3]
+}
+
+fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = f64::sqrt(1.0 - sin2_theta_t);
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + f64::exp(-x / s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64) -> f64 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * f64::ln(1.0 / (u * k + logistic_cdf(a, s)) - 1.0);
+    x.clamp(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adrt::{
+        sampler::IndependentSampler,
+        utility::Point,
+        vec3::{dot_product, Vec3},
+    };
+
+    #[test]
+    fn scattered_directions_are_normalized_and_weights_finite() {
+        let hair = Hair::from_melanin(1.3, 0.2, 0.3, 0.3);
+        let mut sampler = IndependentSampler::new(11);
+        let mut scattered_count = 0;
+
+        for sample in 0..2000u32 {
+            sampler.start_pixel_sample((0, 0), sample);
+            let mut record = HitRecord::new();
+            record.pt = Point::from(0.0, 0.0, -2.0);
+            record.tangent = Vec3::from(1.0, 0.0, 0.0);
+            record.normal = Vec3::from(0.0, 0.0, 1.0);
+            record.v = (sample % 100) as f64 / 99.0;
+            let direction = Vec3::from(0.3 * (sample % 7) as f64 - 0.9, 0.2, -1.0);
+            let ray_in = Ray::from(Point::new(), direction);
+
+            let mut attenuation = Color::new();
+            let mut scattered = Ray::new();
+            if !hair.scatter(
+                &ray_in,
+                &record,
+                &mut attenuation,
+                &mut scattered,
+                &mut sampler,
+            ) {
+                continue;
+            }
+            scattered_count += 1;
+
+            for value in [attenuation.x(), attenuation.y(), attenuation.z()] {
+                assert!(value.is_finite() && value >= 0.0, "{:?}", attenuation);
+            }
+            let length = dot_product(scattered.direction(), scattered.direction()).sqrt();
+            assert!((length - 1.0).abs() < 1e-9, "length {}", length);
+        }
+        assert!(scattered_count > 1000);
+    }
+}
@@ -2,6 +2,8 @@ use crate::adrt::interval::Interval;
 use crate::adrt::utility::Point;
 
 use super::{
+    aabb::Aabb,
+    checkpoint::Fingerprint,
     material::MaterialType,
     ray::Ray,
     vec3::{dot_product, Vec3},
@@ -13,6 +15,9 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub material: MaterialType,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    pub tangent: Vec3,
     pub front_face: bool,
 }
 
@@ -23,6 +28,9 @@ impl HitRecord {
             normal: Vec3::new(),
             material: MaterialType::NONE,
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vec3::new(),
             front_face: false,
         }
     }
@@ -31,7 +39,7 @@ impl HitRecord {
         // Sets the hit record normal vector.
         // NOTE: the parameter `outward_noramal` is assumed to have unit length.
 
-        self.front_face = dot_product(ray.direction(), &outward_normal) < 0.0;
+        self.front_face = dot_product(ray.direction(), outward_normal) < 0.0;
         self.normal = if self.front_face {
             *outward_normal
         } else {
@@ -40,6 +48,38 @@ impl HitRecord {
     }
 }
 
-pub trait Hittable {
+/// Scene objects are shared between the render threads, so they have to be
+/// `Send + Sync`.
+pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, ray_t: &Interval, record: &mut HitRecord) -> bool;
+
+    /// Any-hit query: returns whether anything intersects the ray within
+    /// `ray_t`, stopping at the first intersection found instead of searching
+    /// for the closest one.
+    fn occluded(&self, ray: &Ray, ray_t: &Interval) -> bool;
+
+    fn bounding_box(&self) -> Aabb;
+
+    /// Feeds everything that changes how the object looks, its geometry and
+    /// its materials, into `fingerprint`. Checkpoints are only resumed for
+    /// scenes with the same fingerprint.
+    fn fingerprint(&self, fingerprint: &mut Fingerprint);
+}
+
+impl<T: Hittable + ?Sized> Hittable for Box<T> {
+    fn hit(&self, ray: &Ray, ray_t: &Interval, record: &mut HitRecord) -> bool {
+        (**self).hit(ray, ray_t, record)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: &Interval) -> bool {
+        (**self).occluded(ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
+
+    fn fingerprint(&self, fingerprint: &mut Fingerprint) {
+        (**self).fingerprint(fingerprint)
+    }
 }
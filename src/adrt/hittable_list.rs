@@ -1,10 +1,11 @@
 use crate::adrt::hittable::{HitRecord, Hittable};
 use std::vec::Vec;
 
-use super::interval::Interval;
+use super::{aabb::Aabb, checkpoint::Fingerprint, interval::Interval};
 
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 #[allow(dead_code)]
@@ -12,20 +13,25 @@ impl HittableList {
     pub fn new() -> HittableList {
         HittableList {
             objects: Vec::new(),
+            bbox: Aabb::EMPTY,
         }
     }
 
     pub fn from(object: Box<dyn Hittable>) -> HittableList {
+        let bbox = object.bounding_box();
         HittableList {
             objects: vec![object],
+            bbox,
         }
     }
 
     pub fn clear(&mut self) {
-        self.objects.clear()
+        self.objects.clear();
+        self.bbox = Aabb::EMPTY;
     }
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.bbox = self.bbox.union(&object.bounding_box());
         self.objects.push(object);
     }
 }
@@ -50,4 +56,22 @@ impl Hittable for HittableList {
 
         hit_anything
     }
+
+    fn occluded(&self, ray: &super::ray::Ray, ray_t: &Interval) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.occluded(ray, ray_t))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn fingerprint(&self, fingerprint: &mut Fingerprint) {
+        fingerprint.write_str("list");
+        fingerprint.write_u64(self.objects.len() as u64);
+        for object in self.objects.iter() {
+            object.fingerprint(fingerprint);
+        }
+    }
 }
@@ -0,0 +1,393 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{
+    error::RenderError,
+    stream::{self, StreamWriter},
+    tile::Tile,
+    utility::{write_color, Color},
+};
+
+/// Linear HDR RGB image, as produced by `Camera::render` before any clamping
+/// or gamma encoding. Pixels are stored row by row, starting at the top
+/// left.
+#[derive(Debug, Clone)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    // Position of the top left pixel in the full frame, set for crop renders
+    origin: (usize, usize),
+    // Width and height of the full frame
+    frame_size: (usize, usize),
+}
+
+/// File formats an `Image` can be encoded in.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Binary 8-bit PPM, gamma encoded and clamped to `[0, 1]`.
+    Ppm,
+    /// Portable float map with 32-bit floats, which keeps the HDR values.
+    Pfm,
+}
+
+impl ImageFormat {
+    /// Picks the format from the extension of the path.
+    pub fn from_path(file_path: &str) -> Option<Self> {
+        let extension = Path::new(file_path).extension()?.to_str()?;
+        match extension.to_ascii_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Image {
+    /// Creates a black image.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::new(); width * height],
+            origin: (0, 0),
+            frame_size: (width, height),
+        }
+    }
+
+    /// Creates an image from `width * height` pixels stored row by row.
+    pub fn from(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "pixel count must match the image size"
+        );
+        Self {
+            width,
+            height,
+            pixels,
+            origin: (0, 0),
+            frame_size: (width, height),
+        }
+    }
+
+    /// Loads a binary PPM (P6) or a portable float map. PPM values are
+    /// decoded to the center of their quantization step, so that writing the
+    /// image again gives the same values.
+    pub fn load(file_path: &str) -> Result<Self, RenderError> {
+        let data = fs::read(file_path).map_err(RenderError::io(file_path))?;
+        let invalid = |message: &str| RenderError::ImageDecode {
+            path: file_path.to_string(),
+            message: message.to_string(),
+        };
+
+        let (tokens, pos) = read_header(&data, 4);
+        if tokens.len() < 4 {
+            return Err(invalid("incomplete image header"));
+        }
+        let parse = |token: &str| {
+            token
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid image header"))
+        };
+        let width = parse(&tokens[1])?;
+        let height = parse(&tokens[2])?;
+        // A single whitespace character separates header and data.
+        let raster = &data[(pos + 1).min(data.len())..];
+
+        let pixels = match tokens[0].as_str() {
+            "P6" => {
+                let max_value = parse(&tokens[3])?;
+                if max_value == 0 {
+                    return Err(invalid("invalid image header"));
+                }
+                let bytes_per_value = if max_value > 255 { 2 } else { 1 };
+                let raster = raster
+                    .get(..width * height * 3 * bytes_per_value)
+                    .ok_or_else(|| invalid("truncated image data"))?;
+                let values: Vec<f64> = raster
+                    .chunks(bytes_per_value)
+                    .map(|c| {
+                        let value = if bytes_per_value == 2 {
+                            u16::from_be_bytes([c[0], c[1]]) as usize
+                        } else {
+                            c[0] as usize
+                        };
+                        let gamma = ((value as f64 + 0.5) / max_value as f64).min(1.0);
+                        gamma * gamma
+                    })
+                    .collect();
+                values
+                    .chunks(3)
+                    .map(|c| Color::from(c[0], c[1], c[2]))
+                    .collect()
+            }
+            "PF" | "Pf" => {
+                let scale = tokens[3]
+                    .parse::<f64>()
+                    .map_err(|_| invalid("invalid image header"))?;
+                let channels = if tokens[0] == "PF" { 3 } else { 1 };
+                let raster = raster
+                    .get(..width * height * channels * 4)
+                    .ok_or_else(|| invalid("truncated image data"))?;
+                let values: Vec<f64> = raster
+                    .chunks(4)
+                    .map(|c| {
+                        let bytes = [c[0], c[1], c[2], c[3]];
+                        let value = if scale < 0.0 {
+                            f32::from_le_bytes(bytes)
+                        } else {
+                            f32::from_be_bytes(bytes)
+                        };
+                        value as f64 * scale.abs()
+                    })
+                    .collect();
+                // Rows are stored from the bottom up.
+                let mut pixels = Vec::with_capacity(width * height);
+                for row in values.chunks((width * channels).max(1)).rev() {
+                    pixels.extend(
+                        row.chunks(channels)
+                            .map(|c| Color::from(c[0], c[channels / 2], c[channels - 1])),
+                    );
+                }
+                pixels
+            }
+            _ => return Err(invalid("only P6 PPM images and PFM images are supported")),
+        };
+
+        Ok(Self::from(width, height, pixels))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    /// Position of the top left pixel within the full frame, which is not
+    /// zero for renders with a crop window.
+    pub fn origin(&self) -> (usize, usize) {
+        self.origin
+    }
+
+    pub fn set_origin(&mut self, x: usize, y: usize) {
+        self.origin = (x, y);
+    }
+
+    /// Width and height of the full frame the image is part of, which is
+    /// larger than the image for renders with a crop window.
+    pub fn frame_size(&self) -> (usize, usize) {
+        self.frame_size
+    }
+
+    pub fn set_frame_size(&mut self, width: usize, height: usize) {
+        self.frame_size = (width, height);
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Copies `image` into this image at its origin.
+    pub fn paste(&mut self, image: &Image) -> Result<(), RenderError> {
+        let (x, y) = image.origin;
+        if x + image.width > self.width || y + image.height > self.height {
+            return Err(RenderError::SizeMismatch(format!(
+                "a {}x{} image at {},{} does not fit into a {}x{} image",
+                image.width, image.height, x, y, self.width, self.height
+            )));
+        }
+
+        for (row, image_row) in image.pixels.chunks(image.width.max(1)).enumerate() {
+            let start = (y + row) * self.width + x;
+            self.pixels[start..start + image.width].copy_from_slice(image_row);
+        }
+        Ok(())
+    }
+
+    /// Pastes the image at its origin into the image file at `file_path`,
+    /// e.g. to replace a noisy area of a full render with a crop render. The
+    /// file has to hold an image of the size of the full frame.
+    pub fn composite_into(&self, file_path: &str) -> Result<(), RenderError> {
+        let mut full_image = Image::load(file_path)?;
+        let mismatch = |message: String| RenderError::ImageDecode {
+            path: file_path.to_string(),
+            message,
+        };
+        if (full_image.width, full_image.height) != self.frame_size {
+            return Err(mismatch(format!(
+                "the image is {}x{}, but the crop was rendered for a {}x{} frame",
+                full_image.width, full_image.height, self.frame_size.0, self.frame_size.1
+            )));
+        }
+        full_image
+            .paste(self)
+            .map_err(|error| mismatch(error.to_string()))?;
+        full_image.save(file_path)
+    }
+
+    /// Encodes the image in the given format.
+    pub fn write(&self, writer: &mut impl Write, format: ImageFormat) -> std::io::Result<()> {
+        match format {
+            ImageFormat::Ppm => {
+                writer.write_all(format!("P6\n{} {} 255\n", self.width, self.height).as_bytes())?;
+                for pixel in self.pixels.iter() {
+                    write_color(writer, *pixel)?;
+                }
+            }
+            ImageFormat::Pfm => {
+                // A negative scale marks little-endian data, rows run from the bottom up.
+                writer
+                    .write_all(format!("PF\n{} {}\n-1.0\n", self.width, self.height).as_bytes())?;
+                for row in self.pixels.chunks(self.width.max(1)).rev() {
+                    for pixel in row {
+                        for value in [pixel.x(), pixel.y(), pixel.z()] {
+                            writer.write_all(&(value as f32).to_le_bytes())?;
+                        }
+                    }
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    /// Passes the image to a stream writer as a single block.
+    pub fn write_stream(&self, writer: &mut dyn StreamWriter) -> Result<(), RenderError> {
+        writer.start(self.width, self.height)?;
+        if let Some(size) = writer.tile_size() {
+            // Tile writers take one tile at a time.
+            for y in (0..self.height).step_by(size.max(1)) {
+                for x in (0..self.width).step_by(size.max(1)) {
+                    let block = Tile {
+                        x: x as i32,
+                        y: y as i32,
+                        width: size.min(self.width - x) as i32,
+                        height: size.min(self.height - y) as i32,
+                    };
+                    let pixels: Vec<Color> = (y..y + block.height as usize)
+                        .flat_map(|row| {
+                            self.pixels[row * self.width + x..][..block.width as usize].to_vec()
+                        })
+                        .collect();
+                    writer.write_block(&block, &pixels)?;
+                }
+            }
+        } else {
+            let block = Tile {
+                x: 0,
+                y: 0,
+                width: self.width as i32,
+                height: self.height as i32,
+            };
+            writer.write_block(&block, &self.pixels)?;
+        }
+        writer.finish()
+    }
+
+    /// Writes the image to a file, in the format given by its extension.
+    /// PNG and TIFF files are written by the stream writers.
+    pub fn save(&self, file_path: &str) -> Result<(), RenderError> {
+        let Some(format) = ImageFormat::from_path(file_path) else {
+            return self.write_stream(stream::create(file_path)?.as_mut());
+        };
+        let mut file = BufWriter::new(File::create(file_path).map_err(RenderError::io(file_path))?);
+        self.write(&mut file, format)
+            .map_err(RenderError::io(file_path))
+    }
+}
+
+/// Splits off up to `count` whitespace separated tokens of a PNM style
+/// header, skipping comments from `#` to the end of the line. Returns the
+/// tokens and the position right after the last one.
+pub(crate) fn read_header(data: &[u8], count: usize) -> (Vec<String>, usize) {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while tokens.len() < count && pos < data.len() {
+        if data[pos] == b'#' {
+            while pos < data.len() && data[pos] != b'\n' {
+                pos += 1;
+            }
+        } else if data[pos].is_ascii_whitespace() {
+            pos += 1;
+        } else {
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            tokens.push(String::from_utf8_lossy(&data[start..pos]).to_string());
+        }
+    }
+    (tokens, pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rust_tracer_{}_{}", std::process::id(), name))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn composite_requires_the_full_frame_size() {
+        let mut crop = Image::from(2, 1, vec![Color::from(1.0, 1.0, 1.0); 2]);
+        crop.set_origin(1, 2);
+        crop.set_frame_size(4, 3);
+
+        let wrong_size = temp_path("wrong_size.pfm");
+        Image::new(8, 6).save(&wrong_size).unwrap();
+        let result = crop.composite_into(&wrong_size);
+        let _ = fs::remove_file(&wrong_size);
+        assert!(matches!(result, Err(RenderError::ImageDecode { path, .. }) if path == wrong_size));
+
+        let mut outside = crop.clone();
+        outside.set_origin(3, 2);
+        assert!(matches!(
+            Image::new(4, 3).paste(&outside),
+            Err(RenderError::SizeMismatch(_))
+        ));
+
+        let full_frame = temp_path("full_frame.pfm");
+        Image::new(4, 3).save(&full_frame).unwrap();
+        crop.composite_into(&full_frame).unwrap();
+        let composite = Image::load(&full_frame).unwrap();
+        let _ = fs::remove_file(&full_frame);
+        assert_eq!(composite.pixel(1, 2).x(), 1.0);
+        assert_eq!(composite.pixel(2, 2).x(), 1.0);
+        assert_eq!(composite.pixel(3, 2).x(), 0.0);
+        assert_eq!(composite.pixel(0, 2).x(), 0.0);
+        assert_eq!(composite.pixel(1, 1).x(), 0.0);
+    }
+
+    #[test]
+    fn undecodable_images_are_reported() {
+        let file_path = temp_path("broken.ppm");
+        fs::write(&file_path, b"P6\n4 4 255\n\x00\x01").unwrap();
+        let result = Image::load(&file_path);
+        let _ = fs::remove_file(&file_path);
+        assert!(matches!(result, Err(RenderError::ImageDecode { .. })));
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use super::{
+    aabb::Aabb,
+    bvh::Bvh,
+    checkpoint::Fingerprint,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    ray::Ray,
+    transform::Transform,
+    utility::Point,
+    vec3::unit_vector,
+};
+
+/// Top-level acceleration structure over instances that share bottom-level
+/// hierarchies. Use `Bvh::object_mut` and `Bvh::refit` to animate instances
+/// without rebuilding it.
+#[allow(dead_code)]
+pub type Tlas = Bvh<Instance>;
+
+/// Places a shared object in the scene with its own transformation.
+///
+/// The object itself, usually a `Bvh` over the primitives of one model, is
+/// reference counted and shared between threads, so thousands of instances
+/// only cost one copy of the geometry. A `Bvh<Instance>` forms the top level
+/// of a two-level hierarchy.
+///
+/// Mirroring transforms with a negative determinant are supported: normals
+/// are transformed with the inverse transpose, which keeps the sign of their
+/// dot product with the ray direction, so `front_face` and the orientation
+/// of the normal towards the ray stay valid in world space.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    transform: Transform,
+    bbox: Aabb,
+}
+
+#[allow(dead_code)]
+impl Instance {
+    pub fn from(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        let bbox = transform_box(&object.bounding_box(), &transform);
+        Self {
+            object,
+            transform,
+            bbox,
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    /// Moves the instance. The bounding volume hierarchy that contains the
+    /// instance has to be refitted afterwards.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.bbox = transform_box(&self.object.bounding_box(), &transform);
+    }
+
+    /// Transforms the ray into object space. The direction isn't normalized,
+    /// so the ray parameter `t` is the same in both spaces.
+    fn object_ray(&self, ray: &Ray) -> Ray {
+        let inverse = self.transform.inverse();
+        Ray::from(inverse.point(ray.origin()), inverse.vector(ray.direction()))
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, ray_t: &Interval, record: &mut HitRecord) -> bool {
+        if !self.object.hit(&self.object_ray(ray), ray_t, record) {
+            return false;
+        }
+
+        record.pt = self.transform.point(&record.pt);
+        record.normal = unit_vector(self.transform.normal(&record.normal));
+        if !record.tangent.near_zero() {
+            record.tangent = unit_vector(self.transform.vector(&record.tangent));
+        }
+
+        true
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t: &Interval) -> bool {
+        self.object.occluded(&self.object_ray(ray), ray_t)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn fingerprint(&self, fingerprint: &mut Fingerprint) {
+        fingerprint.write_str("instance");
+        fingerprint.write_str(&format!("{:?}", self.transform));
+        fingerprint.write_shared(&self.object);
+    }
+}
+
+/// Returns the box that encloses all eight transformed corners of `bbox`.
+fn transform_box(bbox: &Aabb, transform: &Transform) -> Aabb {
+    let mut result = Aabb::EMPTY;
+    for i in 0..8 {
+        let corner = Point::from(
+            if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+            if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+            if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+        );
+        let p = transform.point(&corner);
+        result = result.union(&Aabb::from_points(p, p));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adrt::{
+        material::MaterialType,
+        sphere::Sphere,
+        utility::Color,
+        vec3::{dot_product, Vec3},
+    };
+
+    #[test]
+    fn mirrored_instances_keep_front_faces() {
+        let sphere = Sphere::from(
+            Point::from(1.0, 0.0, 0.0),
+            0.5,
+            MaterialType::LAMBERTIAN(Color::from(0.5, 0.5, 0.5)),
+        );
+        let mirror = Transform::scale(-1.0, 1.0, 1.0);
+        let instance = Instance::from(Arc::new(sphere), mirror);
+        let ray_t = Interval::from(0.001, f64::INFINITY);
+
+        // From outside, the mirrored sphere at x = -1 is hit on its front face.
+        let ray = Ray::from(Point::from(-4.0, 0.0, 0.0), Vec3::from(1.0, 0.0, 0.0));
+        let mut record = HitRecord::new();
+        assert!(instance.hit(&ray, &ray_t, &mut record));
+        assert!(record.front_face);
+        assert!((record.pt.x() + 1.5).abs() < 1e-9);
+        assert!(dot_product(ray.direction(), &record.normal) < 0.0);
+
+        // From its center, the inside is hit on the back face.
+        let ray = Ray::from(Point::from(-1.0, 0.0, 0.0), Vec3::from(1.0, 0.0, 0.0));
+        let mut record = HitRecord::new();
+        assert!(instance.hit(&ray, &ray_t, &mut record));
+        assert!(!record.front_face);
+        assert!(dot_product(ray.direction(), &record.normal) < 0.0);
+    }
+}
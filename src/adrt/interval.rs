@@ -1,3 +1,4 @@
+#[derive(Debug, Clone, Copy)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -26,6 +27,28 @@ impl Interval {
         Self { min, max }
     }
 
+    /// Creates the smallest interval that encloses both `a` and `b`.
+    pub fn from_intervals(a: &Interval, b: &Interval) -> Self {
+        Self {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    /// Returns the interval padded by `delta / 2` on both sides.
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::from(self.min - padding, self.max + padding)
+    }
+
+    pub fn union(&self, other: &Interval) -> Interval {
+        Interval::from_intervals(self, other)
+    }
+
     pub fn contains(&self, x: f64) -> bool {
         self.min < x && x < self.max
     }
@@ -44,3 +67,25 @@ impl Interval {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_expand_and_union() {
+        let interval = Interval::from(1.0, 3.0);
+        assert_eq!(interval.size(), 2.0);
+
+        let expanded = interval.expand(1.0);
+        assert_eq!((expanded.min, expanded.max), (0.5, 3.5));
+
+        let union = interval.union(&Interval::from(-2.0, 0.0));
+        assert_eq!((union.min, union.max), (-2.0, 3.0));
+
+        // The empty interval is the identity of the union.
+        let union = Interval::EMPTY.union(&interval);
+        assert_eq!((union.min, union.max), (1.0, 3.0));
+        assert!(Interval::EMPTY.size() < 0.0);
+    }
+}
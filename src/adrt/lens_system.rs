@@ -0,0 +1,605 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use super::{
+    error::RenderError,
+    ray::Ray,
+    rng::Pcg32,
+    utility::{random, Point},
+    vec3::{dot_product, unit_vector, Vec3},
+};
+
+/// Number of radial film segments the exit pupil bounds are precomputed for.
+const EXIT_PUPIL_SEGMENTS: usize = 64;
+/// Samples per axis used to find the exit pupil bounds of one segment.
+const EXIT_PUPIL_SAMPLES: usize = 64;
+
+/// One interface of a lens prescription, all lengths in millimeters.
+#[derive(Debug, Clone, Copy)]
+pub struct LensElement {
+    /// Radius of the spherical surface, positive if its center lies towards
+    /// the film. Zero marks the aperture stop.
+    pub curvature_radius: f64,
+    /// Distance to the next interface along the optical axis.
+    pub thickness: f64,
+    /// Index of refraction of the medium behind the interface, 0 or 1 for air.
+    pub eta: f64,
+    /// Diameter of the interface.
+    pub aperture_diameter: f64,
+}
+
+/// Multi-element lens described by a prescription table, as used by pbrt's
+/// `RealisticCamera`. Rays are traced from the film through every element,
+/// so distortion, vignetting and focus breathing follow from the optics.
+///
+/// The elements are listed from the scene side towards the film.
+#[derive(Debug, Clone)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+    /// Diagonal of the film in millimeters, 43.27 for full frame 35mm.
+    pub film_diagonal: f64,
+    /// Diameter of the aperture stop in millimeters. Values larger than the
+    /// stop in the prescription are clamped to it.
+    pub aperture_diameter: f64,
+    /// World units per millimeter, 0.001 for scenes modelled in meters.
+    pub units_per_mm: f64,
+}
+
+/// A lens system that has been scaled to world units and focused, ready to
+/// generate rays.
+#[derive(Debug, Clone)]
+pub(crate) struct FocusedLens {
+    interfaces: Vec<Interface>,
+    film_extent: (f64, f64),
+    exit_pupil_bounds: Vec<PupilBounds>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    curvature_radius: f64,
+    thickness: f64,
+    eta: f64,
+    aperture_radius: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PupilBounds {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+#[allow(dead_code)]
+impl LensSystem {
+    pub fn from(elements: Vec<LensElement>, film_diagonal: f64) -> Self {
+        let aperture_diameter = elements
+            .iter()
+            .filter(|e| e.curvature_radius == 0.0)
+            .map(|e| e.aperture_diameter)
+            .fold(0.0, f64::max);
+
+        Self {
+            elements,
+            film_diagonal,
+            aperture_diameter,
+            units_per_mm: 0.001,
+        }
+    }
+
+    /// Loads a prescription in the format of pbrt's lens files: one element
+    /// per line with curvature radius, thickness, index of refraction and
+    /// aperture diameter. Lines starting with `#` are comments.
+    pub fn load(file_path: &str, film_diagonal: f64) -> Result<Self, RenderError> {
+        let reader = BufReader::new(File::open(file_path).map_err(RenderError::io(file_path))?);
+        let mut elements = Vec::new();
+        let invalid = |message: String| RenderError::SceneLoad {
+            path: file_path.to_string(),
+            message,
+        };
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line.map_err(RenderError::io(file_path))?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .ok()
+                .filter(|values| values.len() == 4)
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "line {}: expected radius, thickness, eta and aperture diameter",
+                        line_number + 1
+                    ))
+                })?;
+
+            elements.push(LensElement {
+                curvature_radius: values[0],
+                thickness: values[1],
+                eta: values[2],
+                aperture_diameter: values[3],
+            });
+        }
+
+        if elements.is_empty() {
+            return Err(invalid("no lens elements found".to_string()));
+        }
+
+        Ok(Self::from(elements, film_diagonal))
+    }
+
+    /// Double Gauss lens with a focal length of 50mm (US patent 2,673,491),
+    /// on a full frame film.
+    pub fn double_gauss_50mm() -> Self {
+        let prescription = [
+            (29.475, 3.76, 1.67, 25.2),
+            (84.83, 0.12, 1.0, 25.2),
+            (19.275, 4.025, 1.67, 23.0),
+            (40.77, 3.275, 1.699, 23.0),
+            (12.75, 5.705, 1.0, 18.0),
+            (0.0, 4.5, 0.0, 17.1),
+            (-14.495, 1.18, 1.603, 17.0),
+            (40.77, 6.065, 1.658, 20.0),
+            (-20.385, 0.19, 1.0, 20.0),
+            (437.065, 3.22, 1.717, 20.0),
+            (-39.73, 0.0, 1.0, 20.0),
+        ];
+
+        Self::from(
+            prescription
+                .iter()
+                .map(
+                    |&(curvature_radius, thickness, eta, aperture_diameter)| LensElement {
+                        curvature_radius,
+                        thickness,
+                        eta,
+                        aperture_diameter,
+                    },
+                )
+                .collect(),
+            43.27,
+        )
+    }
+
+    /// Scales the lens to world units, moves the film so that objects at
+    /// `focus_distance` are sharp and precomputes the exit pupil. Fails if the
+    /// lens cannot focus at that distance.
+    pub(crate) fn focus(
+        &self,
+        focus_distance: f64,
+        aspect_ratio: f64,
+    ) -> Result<FocusedLens, RenderError> {
+        let scale = self.units_per_mm;
+        let interfaces = self
+            .elements
+            .iter()
+            .map(|e| {
+                let aperture_diameter = if e.curvature_radius == 0.0 {
+                    self.aperture_diameter.min(e.aperture_diameter)
+                } else {
+                    e.aperture_diameter
+                };
+                Interface {
+                    curvature_radius: e.curvature_radius * scale,
+                    thickness: e.thickness * scale,
+                    eta: e.eta,
+                    aperture_radius: aperture_diameter * scale / 2.0,
+                }
+            })
+            .collect();
+
+        let diagonal = self.film_diagonal * scale;
+        let film_width =
+            f64::sqrt(diagonal * diagonal / (1.0 + 1.0 / (aspect_ratio * aspect_ratio)));
+        let film_height = film_width / aspect_ratio;
+
+        let mut lens = FocusedLens {
+            interfaces,
+            film_extent: (film_width, film_height),
+            exit_pupil_bounds: Vec::new(),
+        };
+
+        let film_distance = lens.focus_thick_lens(focus_distance)?;
+        if let Some(last) = lens.interfaces.last_mut() {
+            last.thickness = film_distance;
+        }
+
+        // The film positions are jittered with a fixed seed, so the pupil
+        // bounds and thus the image are the same on every run.
+        let mut rng = Pcg32::new(0, 0);
+        lens.exit_pupil_bounds = (0..EXIT_PUPIL_SEGMENTS)
+            .map(|i| {
+                let r0 = i as f64 / EXIT_PUPIL_SEGMENTS as f64 * diagonal / 2.0;
+                let r1 = (i + 1) as f64 / EXIT_PUPIL_SEGMENTS as f64 * diagonal / 2.0;
+                lens.bound_exit_pupil(r0, r1, &mut rng)
+            })
+            .collect();
+
+        Ok(lens)
+    }
+}
+
+impl FocusedLens {
+    /// Generates a ray in camera coordinates (x right, y up, z towards the
+    /// scene) for the film position `(s, t)`, where both run from 0 to 1
+    /// across the image with `t` pointing down, and the lens sample `u` in
+    /// `[0, 1)^2`. Returns the ray together with its weight, or `None` if the
+    /// lens blocks it.
+    pub(crate) fn generate_ray(&self, s: f64, t: f64, u: (f64, f64)) -> Option<(Ray, f64)> {
+        // The lens flips the image, so the film is mirrored in x and y.
+        let film = Point::from(
+            -(s - 0.5) * self.film_extent.0,
+            (t - 0.5) * self.film_extent.1,
+            0.0,
+        );
+
+        let (rear, bounds_area) = self.sample_exit_pupil(film.x(), film.y(), u);
+        let film_ray = Ray::from(film, rear - film);
+        let ray = self.trace_from_film(&film_ray)?;
+
+        // Irradiance falls off with cos^4 of the angle to the optical axis and
+        // scales with the area the rays were sampled from.
+        let cos_theta = unit_vector(*film_ray.direction()).z();
+        let cos4_theta = (cos_theta * cos_theta) * (cos_theta * cos_theta);
+        let weight = cos4_theta * bounds_area / self.exit_pupil_bounds[0].area();
+
+        Some((ray, weight))
+    }
+
+    fn rear_z(&self) -> f64 {
+        self.interfaces.last().map_or(0.0, |i| i.thickness)
+    }
+
+    fn front_z(&self) -> f64 {
+        self.interfaces.iter().map(|i| i.thickness).sum()
+    }
+
+    fn rear_radius(&self) -> f64 {
+        self.interfaces.last().map_or(0.0, |i| i.aperture_radius)
+    }
+
+    /// Traces a ray in camera coordinates from the film through all elements
+    /// and returns the ray that leaves the front element.
+    fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        // Lens coordinates have the film at z = 0 and the lens along -z.
+        let mut origin = flip_z(ray.origin());
+        let mut direction = flip_z(ray.direction());
+        let mut element_z = 0.0;
+
+        for i in (0..self.interfaces.len()).rev() {
+            let element = &self.interfaces[i];
+            element_z -= element.thickness;
+
+            let is_stop = element.curvature_radius == 0.0;
+            let (t, normal) = if is_stop {
+                // Refraction may have turned the ray back towards the film.
+                if direction.z() >= 0.0 {
+                    return None;
+                }
+                ((element_z - origin.z()) / direction.z(), Vec3::new())
+            } else {
+                let center = element_z + element.curvature_radius;
+                intersect_spherical_element(element.curvature_radius, center, &origin, &direction)?
+            };
+
+            let hit = origin + t * direction;
+            if hit.x() * hit.x() + hit.y() * hit.y()
+                > element.aperture_radius * element.aperture_radius
+            {
+                return None;
+            }
+            origin = hit;
+
+            if !is_stop {
+                let eta_i = element.eta;
+                let eta_t = if i > 0 && self.interfaces[i - 1].eta != 0.0 {
+                    self.interfaces[i - 1].eta
+                } else {
+                    1.0
+                };
+                direction = refract(
+                    &unit_vector(-direction),
+                    &normal,
+                    eta_i_or_air(eta_i) / eta_t,
+                )?;
+            }
+        }
+
+        Some(Ray::from(flip_z(&origin), flip_z(&direction)))
+    }
+
+    /// Traces a ray in camera coordinates from the scene through all elements
+    /// and returns the ray that leaves the rear element.
+    fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut origin = flip_z(ray.origin());
+        let mut direction = flip_z(ray.direction());
+        let mut element_z = -self.front_z();
+
+        for i in 0..self.interfaces.len() {
+            let element = &self.interfaces[i];
+
+            let is_stop = element.curvature_radius == 0.0;
+            let (t, normal) = if is_stop {
+                ((element_z - origin.z()) / direction.z(), Vec3::new())
+            } else {
+                let center = element_z + element.curvature_radius;
+                intersect_spherical_element(element.curvature_radius, center, &origin, &direction)?
+            };
+
+            let hit = origin + t * direction;
+            if hit.x() * hit.x() + hit.y() * hit.y()
+                > element.aperture_radius * element.aperture_radius
+            {
+                return None;
+            }
+            origin = hit;
+
+            if !is_stop {
+                let eta_i = if i == 0 || self.interfaces[i - 1].eta == 0.0 {
+                    1.0
+                } else {
+                    self.interfaces[i - 1].eta
+                };
+                direction = refract(
+                    &unit_vector(-direction),
+                    &normal,
+                    eta_i / eta_i_or_air(element.eta),
+                )?;
+            }
+            element_z += element.thickness;
+        }
+
+        Some(Ray::from(flip_z(&origin), flip_z(&direction)))
+    }
+
+    /// Finds the film distance that focuses objects at `focus_distance`,
+    /// using the thick lens approximation of the system.
+    fn focus_thick_lens(&self, focus_distance: f64) -> Result<f64, RenderError> {
+        let (pz, fz) = self.thick_lens_approximation()?;
+        let f = fz[0] - pz[0];
+        let z = -focus_distance;
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4.0 * f - pz[0]);
+        // Objects closer than four focal lengths have no real image.
+        if c < 0.0 {
+            return Err(RenderError::InvalidSettings(format!(
+                "the lens system cannot focus at a distance of {}",
+                focus_distance
+            )));
+        }
+        let delta = 0.5 * (pz[1] - z + pz[0] - f64::sqrt(c));
+        Ok(self.rear_z() + delta)
+    }
+
+    /// Returns the z coordinates of the principal planes and focal points,
+    /// first for rays entering from the scene, then from the film. Fails if
+    /// the rays are blocked or leave the lens parallel to the axis.
+    fn thick_lens_approximation(&self) -> Result<([f64; 2], [f64; 2]), RenderError> {
+        let unfocused = || {
+            RenderError::InvalidSettings(
+                "no ray parallel to the optical axis is focused by the lens system".to_string(),
+            )
+        };
+
+        // Trace rays parallel to the axis at a small height in both
+        // directions and see where they cross the axis.
+        let x = 0.001 * self.film_extent.0.hypot(self.film_extent.1);
+
+        let scene_ray = Ray::from(
+            Point::from(x, 0.0, self.front_z() + 1.0),
+            Vec3::from(0.0, 0.0, -1.0),
+        );
+        let (pz0, fz0) = self
+            .trace_from_scene(&scene_ray)
+            .and_then(|film_ray| cardinal_points(&scene_ray, &film_ray))
+            .ok_or_else(unfocused)?;
+
+        let film_ray = Ray::from(
+            Point::from(x, 0.0, self.rear_z() - 1.0),
+            Vec3::from(0.0, 0.0, 1.0),
+        );
+        let (pz1, fz1) = self
+            .trace_from_film(&film_ray)
+            .and_then(|scene_ray| cardinal_points(&film_ray, &scene_ray))
+            .ok_or_else(unfocused)?;
+
+        Ok(([pz0, pz1], [fz0, fz1]))
+    }
+
+    /// Bounds the area of the rear element through which light from the film
+    /// segment between radius `r0` and `r1` reaches the scene.
+    fn bound_exit_pupil(&self, r0: f64, r1: f64, rng: &mut Pcg32) -> PupilBounds {
+        let rear_radius = self.rear_radius();
+        let mut bounds: Option<PupilBounds> = None;
+
+        for yi in 0..EXIT_PUPIL_SAMPLES {
+            for xi in 0..EXIT_PUPIL_SAMPLES {
+                let u = (xi as f64 + 0.5) / EXIT_PUPIL_SAMPLES as f64;
+                let v = (yi as f64 + 0.5) / EXIT_PUPIL_SAMPLES as f64;
+                let film = Point::from(r0 + (r1 - r0) * random(rng), 0.0, 0.0);
+                let rear = Point::from(
+                    -1.5 * rear_radius + 3.0 * rear_radius * u,
+                    -1.5 * rear_radius + 3.0 * rear_radius * v,
+                    self.rear_z(),
+                );
+
+                if self
+                    .trace_from_film(&Ray::from(film, rear - film))
+                    .is_some()
+                {
+                    let p = (rear.x(), rear.y());
+                    bounds = Some(match bounds {
+                        Some(b) => PupilBounds {
+                            min: (b.min.0.min(p.0), b.min.1.min(p.1)),
+                            max: (b.max.0.max(p.0), b.max.1.max(p.1)),
+                        },
+                        None => PupilBounds { min: p, max: p },
+                    });
+                }
+            }
+        }
+
+        // Grow the bounds by one sample spacing to account for the gaps
+        // between the samples. Fall back to the whole rear element if nothing
+        // got through.
+        let spacing = 3.0 * rear_radius / EXIT_PUPIL_SAMPLES as f64;
+        match bounds {
+            Some(b) => PupilBounds {
+                min: (b.min.0 - spacing, b.min.1 - spacing),
+                max: (b.max.0 + spacing, b.max.1 + spacing),
+            },
+            None => PupilBounds {
+                min: (-rear_radius, -rear_radius),
+                max: (rear_radius, rear_radius),
+            },
+        }
+    }
+
+    /// Samples a point on the rear element within the exit pupil bounds of
+    /// the film position and returns it with the area of the bounds.
+    fn sample_exit_pupil(&self, film_x: f64, film_y: f64, (u0, u1): (f64, f64)) -> (Point, f64) {
+        let r_film = film_x.hypot(film_y);
+        let diagonal = self.film_extent.0.hypot(self.film_extent.1);
+        let index = ((r_film / (diagonal / 2.0) * EXIT_PUPIL_SEGMENTS as f64) as usize)
+            .min(EXIT_PUPIL_SEGMENTS - 1);
+        let bounds = self.exit_pupil_bounds[index];
+
+        let lens_x = bounds.min.0 + u0 * (bounds.max.0 - bounds.min.0);
+        let lens_y = bounds.min.1 + u1 * (bounds.max.1 - bounds.min.1);
+
+        // The bounds were computed for film points on the +x axis, so rotate
+        // them to the actual film position.
+        let (sin_theta, cos_theta) = if r_film != 0.0 {
+            (film_y / r_film, film_x / r_film)
+        } else {
+            (0.0, 1.0)
+        };
+
+        (
+            Point::from(
+                cos_theta * lens_x - sin_theta * lens_y,
+                sin_theta * lens_x + cos_theta * lens_y,
+                self.rear_z(),
+            ),
+            bounds.area(),
+        )
+    }
+}
+
+impl PupilBounds {
+    fn area(&self) -> f64 {
+        (self.max.0 - self.min.0) * (self.max.1 - self.min.1)
+    }
+}
+
+fn eta_i_or_air(eta: f64) -> f64 {
+    if eta != 0.0 {
+        eta
+    } else {
+        1.0
+    }
+}
+
+fn flip_z(v: &Vec3) -> Vec3 {
+    Vec3::from(v.x(), v.y(), -v.z())
+}
+
+/// Intersects the ray with a spherical lens surface centered on the axis at
+/// `center` and returns the ray parameter and the normal facing the ray.
+fn intersect_spherical_element(
+    radius: f64,
+    center: f64,
+    origin: &Point,
+    direction: &Vec3,
+) -> Option<(f64, Vec3)> {
+    let o = *origin - Vec3::from(0.0, 0.0, center);
+    let a = direction.length_squared();
+    let b = 2.0 * dot_product(direction, &o);
+    let c = o.length_squared() - radius * radius;
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrt_disc = f64::sqrt(discriminant);
+    let t0 = (-b - sqrt_disc) / (2.0 * a);
+    let t1 = (-b + sqrt_disc) / (2.0 * a);
+
+    // Pick the intersection on the side of the sphere the lens surface is on.
+    let use_closer = (direction.z() > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = unit_vector(o + t * *direction);
+    let normal = if dot_product(&normal, direction) > 0.0 {
+        -normal
+    } else {
+        normal
+    };
+
+    Some((t, normal))
+}
+
+/// Refracts the incoming direction `wi`, pointing away from the surface,
+/// with the relative index of refraction `eta`. Returns `None` on total
+/// internal reflection.
+fn refract(wi: &Vec3, normal: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = dot_product(normal, wi);
+    let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    let sin2_theta_t = eta * eta * sin2_theta_i;
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = f64::sqrt(1.0 - sin2_theta_t);
+
+    Some(eta * -*wi + (eta * cos_theta_i - cos_theta_t) * *normal)
+}
+
+/// Computes the z coordinates of the principal plane and the focal point
+/// from a ray parallel to the axis and the ray leaving the lens system, or
+/// `None` if the leaving ray never crosses the axis.
+fn cardinal_points(ray_in: &Ray, ray_out: &Ray) -> Option<(f64, f64)> {
+    if ray_out.direction().x() == 0.0 {
+        return None;
+    }
+    let tf = -ray_out.origin().x() / ray_out.direction().x();
+    let fz = -ray_out.at(tf).z();
+    let tp = (ray_in.origin().x() - ray_out.origin().x()) / ray_out.direction().x();
+    let pz = -ray_out.at(tp).z();
+    Some((pz, fz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_gauss_focuses_at_a_finite_distance() {
+        let lens = LensSystem::double_gauss_50mm().focus(10.0, 1.5).unwrap();
+        let film_distance = lens.rear_z();
+        assert!(film_distance.is_finite() && film_distance > 0.0);
+
+        // Focusing closer moves the film away from the lens.
+        let close = LensSystem::double_gauss_50mm().focus(1.0, 1.5).unwrap();
+        assert!(close.rear_z() > film_distance);
+
+        let (ray, weight) = lens
+            .generate_ray(0.5, 0.5, (0.5, 0.5))
+            .expect("the centre of the film sees through the lens");
+        let direction = unit_vector(*ray.direction());
+        assert!(direction.z() > 0.99);
+        assert!(weight.is_finite() && weight > 0.0);
+    }
+
+    #[test]
+    fn focusing_closer_than_the_lens_allows_fails() {
+        let result = LensSystem::double_gauss_50mm().focus(0.1, 1.5);
+        assert!(matches!(result, Err(RenderError::InvalidSettings(_))));
+    }
+}
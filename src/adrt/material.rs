@@ -1,15 +1,19 @@
 use super::{
+    hair::Hair,
     hittable::HitRecord,
     ray::Ray,
+    sampler::Sampler,
     utility::Color,
-    vec3::{random_unit_vector, reflect, unit_vector},
+    vec3::{reflect, sample_unit_sphere, unit_vector},
 };
 
+#[allow(clippy::upper_case_acronyms, dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub(crate) enum MaterialType {
     NONE,
     LAMBERTIAN(Color),
     METAL(Color),
+    HAIR(Hair),
 }
 
 impl MaterialType {
@@ -19,13 +23,17 @@ impl MaterialType {
         hit_record: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         match self {
             MaterialType::LAMBERTIAN(c) => {
-                MaterialType::lambertian_scatter(&c, hit_record, attenuation, scattered)
+                MaterialType::lambertian_scatter(c, hit_record, attenuation, scattered, sampler)
             }
             MaterialType::METAL(c) => {
-                MaterialType::metal_scatter(&c, ray_in, hit_record, attenuation, scattered)
+                MaterialType::metal_scatter(c, ray_in, hit_record, attenuation, scattered)
+            }
+            MaterialType::HAIR(hair) => {
+                hair.scatter(ray_in, hit_record, attenuation, scattered, sampler)
             }
             MaterialType::NONE => false,
         }
@@ -36,8 +44,9 @@ impl MaterialType {
         hit_record: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        let mut scatter_direction = hit_record.normal + random_unit_vector();
+        let mut scatter_direction = hit_record.normal + sample_unit_sphere(sampler.get_2d());
         if scatter_direction.near_zero() {
             scatter_direction = hit_record.normal;
         }
@@ -0,0 +1,317 @@
+use std::{
+    fmt,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use super::{image::Image, tile::Tile};
+
+/// Receives the progress of a render. All methods do nothing by default, so
+/// implementations only pick the events they need.
+///
+/// `tile_finished` is called from the render threads, the other methods from
+/// the thread that called `Camera::render`.
+pub trait RenderObserver: Send + Sync {
+    /// Called before the first pass. For resumed renders `stats` includes
+    /// the passes loaded from the checkpoint.
+    fn render_started(&self, _width: usize, _height: usize, _stats: &RenderStats) {}
+
+    /// Called whenever a tile of the pass with the given index is finished.
+    fn tile_finished(&self, _pass: u32, _tile: &Tile) {}
+
+    /// Called after every complete pass with the image rendered so far.
+    fn pass_finished(&self, _image: &Image, _stats: &RenderStats) {}
+
+    /// Called once at the end, after the last pass or after a cancellation.
+    fn render_finished(&self, _stats: &RenderStats, _cancelled: bool) {}
+}
+
+impl fmt::Debug for dyn RenderObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RenderObserver")
+    }
+}
+
+/// Statistics of a running render.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderStats {
+    /// Number of finished passes.
+    pub passes: u32,
+    /// Expected number of passes in total. Renders with a time limit or an
+    /// error target update the estimate after every pass.
+    pub planned_passes: u32,
+    /// Number of samples taken in all pixels together.
+    pub samples: u64,
+    /// Mean relative standard error of the pixels, infinite until every
+    /// pixel has two samples.
+    pub mean_relative_error: f64,
+    /// Time since the render started in seconds.
+    pub elapsed: f64,
+}
+
+/// Stops a render from another thread. The render threads finish the tiles
+/// they are working on, a checkpoint is saved if one is configured, and
+/// `Camera::render` returns the image so far, while
+/// `Camera::render_streamed` returns `RenderError::Cancelled`. Clones share
+/// the same state, and a cancelled token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+/// Shows the progress in the terminal with an indicatif progress bar.
+pub struct ProgressBarObserver {
+    bar: ProgressBar,
+    pixel_count: AtomicU64,
+}
+
+/// Ignores all events.
+#[derive(Debug, Clone, Copy)]
+pub struct QuietObserver;
+
+/// Writes every event as one JSON object per line, e.g. for a server that
+/// reports the progress to its clients.
+pub struct JsonLinesObserver {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+#[allow(dead_code)]
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl ProgressBarObserver {
+    pub fn new() -> Self {
+        let sty = ProgressStyle::default_bar()
+            .template(
+                "[{elapsed_precise} | {percent}%] {bar:40.cyan/blue} {pos:>7}/{len:7} \n{msg}",
+            )
+            .expect("Could not create style for progress bar.");
+
+        let bar = ProgressBar::new(0);
+        bar.set_style(sty);
+
+        Self {
+            bar,
+            pixel_count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl RenderObserver for ProgressBarObserver {
+    fn render_started(&self, width: usize, height: usize, stats: &RenderStats) {
+        let pixel_count = (width * height) as u64;
+        self.pixel_count.store(pixel_count, Ordering::Relaxed);
+        self.bar.reset();
+        self.bar
+            .set_length(pixel_count * stats.planned_passes as u64);
+        self.bar.set_position(pixel_count * stats.passes as u64);
+    }
+
+    fn tile_finished(&self, _pass: u32, tile: &Tile) {
+        self.bar.inc((tile.width * tile.height) as u64);
+    }
+
+    fn pass_finished(&self, _image: &Image, stats: &RenderStats) {
+        let pixel_count = self.pixel_count.load(Ordering::Relaxed);
+        self.bar
+            .set_length(pixel_count * stats.planned_passes as u64);
+        self.bar.set_message(format!(
+            "Pass {}, mean relative error {:.4}",
+            stats.passes, stats.mean_relative_error
+        ));
+    }
+
+    fn render_finished(&self, _stats: &RenderStats, cancelled: bool) {
+        if cancelled {
+            self.bar.abandon_with_message("Rendering cancelled.");
+        } else {
+            self.bar
+                .finish_with_message("Rendering finished successfully!");
+        }
+    }
+}
+
+impl RenderObserver for QuietObserver {}
+
+#[allow(dead_code)]
+impl JsonLinesObserver {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    // Progress reports must not stop the render, so write errors are ignored.
+    fn write_line(&self, line: String) {
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{}", line).and_then(|_| writer.flush());
+        }
+    }
+}
+
+impl RenderObserver for JsonLinesObserver {
+    fn render_started(&self, width: usize, height: usize, stats: &RenderStats) {
+        self.write_line(format!(
+            "{{\"event\":\"render_started\",\"width\":{},\"height\":{},{}}}",
+            width,
+            height,
+            stats_json(stats)
+        ));
+    }
+
+    fn tile_finished(&self, pass: u32, tile: &Tile) {
+        self.write_line(format!(
+            "{{\"event\":\"tile_finished\",\"pass\":{},\
+             \"x\":{},\"y\":{},\"width\":{},\"height\":{}}}",
+            pass, tile.x, tile.y, tile.width, tile.height
+        ));
+    }
+
+    fn pass_finished(&self, _image: &Image, stats: &RenderStats) {
+        self.write_line(format!(
+            "{{\"event\":\"pass_finished\",{}}}",
+            stats_json(stats)
+        ));
+    }
+
+    fn render_finished(&self, stats: &RenderStats, cancelled: bool) {
+        self.write_line(format!(
+            "{{\"event\":\"render_finished\",\"cancelled\":{},{}}}",
+            cancelled,
+            stats_json(stats)
+        ));
+    }
+}
+
+fn stats_json(stats: &RenderStats) -> String {
+    // JSON has no infinity, so an unknown error is written as null.
+    let error = if stats.mean_relative_error.is_finite() {
+        stats.mean_relative_error.to_string()
+    } else {
+        "null".to_string()
+    };
+    format!(
+        "\"passes\":{},\"planned_passes\":{},\"samples\":{},\
+         \"mean_relative_error\":{},\"elapsed\":{}",
+        stats.passes, stats.planned_passes, stats.samples, error, stats.elapsed
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writer whose output stays readable after the observer took it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Parses a JSON object of strings, numbers, booleans and nulls into its keys and raw
+    // values, or returns `None` if it isn't valid JSON.
+    fn parse_flat_object(line: &str) -> Option<Vec<(String, String)>> {
+        let body = line.strip_prefix('{')?.strip_suffix('}')?;
+        let mut fields = Vec::new();
+        let mut rest = body;
+        while !rest.is_empty() {
+            let (key, after_key) = rest.strip_prefix('"')?.split_once('"')?;
+            let after_colon = after_key.strip_prefix(':')?;
+            let (value, after_value) = after_colon.split_once(',').unwrap_or((after_colon, ""));
+            let valid = match value {
+                "true" | "false" | "null" => true,
+                _ if value.starts_with('"') => value.len() >= 2 && value.ends_with('"'),
+                _ => {
+                    value
+                        .bytes()
+                        .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b))
+                        && value.parse::<f64>().is_ok()
+                }
+            };
+            if !valid || (after_value.is_empty() && after_colon.len() > value.len()) {
+                return None;
+            }
+            fields.push((key.to_string(), value.to_string()));
+            rest = after_value;
+        }
+        Some(fields)
+    }
+
+    #[test]
+    fn json_lines_are_one_valid_object_each() {
+        let buffer = SharedBuffer::default();
+        let observer = JsonLinesObserver::new(buffer.clone());
+        let mut stats = RenderStats {
+            passes: 0,
+            planned_passes: 2,
+            samples: 0,
+            mean_relative_error: f64::INFINITY,
+            elapsed: 0.0,
+        };
+        observer.render_started(4, 3, &stats);
+        let tile = Tile {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 3,
+        };
+        observer.tile_finished(0, &tile);
+        stats.passes = 1;
+        stats.samples = 12;
+        stats.mean_relative_error = 0.25;
+        stats.elapsed = 1.5;
+        observer.pass_finished(&Image::new(4, 3), &stats);
+        observer.render_finished(&stats, true);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        let objects: Vec<Vec<(String, String)>> = lines
+            .iter()
+            .map(|line| parse_flat_object(line).unwrap_or_else(|| panic!("invalid: {}", line)))
+            .collect();
+
+        let value = |object: &[(String, String)], key: &str| {
+            object
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+                .unwrap()
+        };
+        let events: Vec<String> = objects.iter().map(|o| value(o, "event")).collect();
+        assert_eq!(
+            events,
+            [
+                "\"render_started\"",
+                "\"tile_finished\"",
+                "\"pass_finished\"",
+                "\"render_finished\""
+            ]
+        );
+        assert_eq!(value(&objects[0], "mean_relative_error"), "null");
+        assert_eq!(value(&objects[2], "mean_relative_error"), "0.25");
+        assert_eq!(value(&objects[3], "cancelled"), "true");
+    }
+}
@@ -0,0 +1,167 @@
+use std::f64::consts::PI;
+
+/// Maps positions on the film to viewing directions.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+    /// Pinhole camera, `Camera::vfov` is the vertical field of view.
+    Perspective,
+    /// Parallel rays along the viewing direction. The visible area is the
+    /// one a perspective camera with the same `vfov` sees at `focus_dist`.
+    Orthographic,
+    /// Circular fisheye, `Camera::vfov` is the field of view across the image
+    /// height. Pixels outside of the image circle stay black.
+    Fisheye(FisheyeMapping),
+    /// Full 360° by 180° latitude-longitude panorama, usually rendered with
+    /// an aspect ratio of 2:1.
+    Equirectangular,
+    /// Six 90° faces in a 3x2 layout, usually rendered with an aspect ratio
+    /// of 3:2. The top row holds the right, left and up faces, the bottom
+    /// row the down, front and back faces.
+    Cubemap,
+}
+
+/// Relation between the angle from the optical axis and the distance from
+/// the image center of a fisheye lens.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum FisheyeMapping {
+    /// The distance is proportional to the angle.
+    Equidistant,
+    /// The distance is proportional to `sin(angle / 2)`, which preserves
+    /// solid angles.
+    Equisolid,
+}
+
+impl Projection {
+    /// Returns the direction through the film position `(x, y)`, where both
+    /// run from 0 to 1 across the image with `y` pointing down, for a film of
+    /// the given aspect ratio (width / height).
+    ///
+    /// The direction is given in camera coordinates as its components along
+    /// the right, up and forward axes. Returns `None` for positions that
+    /// don't map to any direction, and for projections that aren't
+    /// direction based (perspective and orthographic).
+    pub fn direction(&self, x: f64, y: f64, aspect: f64, vfov: f64) -> Option<(f64, f64, f64)> {
+        match self {
+            Projection::Perspective | Projection::Orthographic => None,
+            Projection::Fisheye(mapping) => {
+                let nx = (2.0 * x - 1.0) * aspect;
+                let ny = 1.0 - 2.0 * y;
+                let r = f64::sqrt(nx * nx + ny * ny);
+                if r > 1.0 {
+                    return None;
+                }
+
+                let theta_max = vfov.to_radians() / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * theta_max,
+                    FisheyeMapping::Equisolid => {
+                        2.0 * f64::asin((r * f64::sin(theta_max / 2.0)).min(1.0))
+                    }
+                };
+
+                let phi = f64::atan2(ny, nx);
+                let sin_theta = theta.sin();
+                Some((sin_theta * phi.cos(), sin_theta * phi.sin(), theta.cos()))
+            }
+            Projection::Equirectangular => {
+                let longitude = (x - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - y) * PI;
+                Some((
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    latitude.cos() * longitude.cos(),
+                ))
+            }
+            Projection::Cubemap => {
+                let column = ((x * 3.0) as usize).min(2);
+                let row = ((y * 2.0) as usize).min(1);
+                let a = 2.0 * (x * 3.0 - column as f64) - 1.0;
+                let b = 1.0 - 2.0 * (y * 2.0 - row as f64);
+
+                // Forward, right and up axis of every face in camera coordinates.
+                let (forward, right, up) = match (row, column) {
+                    (0, 0) => ((1.0, 0.0, 0.0), (0.0, 0.0, -1.0), (0.0, 1.0, 0.0)),
+                    (0, 1) => ((-1.0, 0.0, 0.0), (0.0, 0.0, 1.0), (0.0, 1.0, 0.0)),
+                    (0, _) => ((0.0, 1.0, 0.0), (1.0, 0.0, 0.0), (0.0, 0.0, -1.0)),
+                    (_, 0) => ((0.0, -1.0, 0.0), (1.0, 0.0, 0.0), (0.0, 0.0, 1.0)),
+                    (_, 1) => ((0.0, 0.0, 1.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)),
+                    (_, _) => ((0.0, 0.0, -1.0), (-1.0, 0.0, 0.0), (0.0, 1.0, 0.0)),
+                };
+
+                let d = (
+                    forward.0 + a * right.0 + b * up.0,
+                    forward.1 + a * right.1 + b * up.1,
+                    forward.2 + a * right.2 + b * up.2,
+                );
+                let length = f64::sqrt(d.0 * d.0 + d.1 * d.1 + d.2 * d.2);
+                Some((d.0 / length, d.1 / length, d.2 / length))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_direction(actual: Option<(f64, f64, f64)>, expected: (f64, f64, f64)) {
+        let (x, y, z) = actual.expect("position should map to a direction");
+        assert!(
+            (x - expected.0).abs() < 1e-9
+                && (y - expected.1).abs() < 1e-9
+                && (z - expected.2).abs() < 1e-9,
+            "expected {:?}, got {:?}",
+            expected,
+            (x, y, z)
+        );
+    }
+
+    #[test]
+    fn equirectangular_center_looks_forward_and_top_row_up() {
+        let projection = Projection::Equirectangular;
+        assert_direction(projection.direction(0.5, 0.5, 2.0, 90.0), (0.0, 0.0, 1.0));
+        assert_direction(projection.direction(0.5, 0.0, 2.0, 90.0), (0.0, 1.0, 0.0));
+        assert_direction(projection.direction(0.75, 0.5, 2.0, 90.0), (1.0, 0.0, 0.0));
+        assert_direction(projection.direction(0.0, 0.5, 2.0, 90.0), (0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn fisheye_maps_the_image_circle() {
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let projection = Projection::Fisheye(mapping);
+            assert_direction(projection.direction(0.5, 0.5, 1.0, 180.0), (0.0, 0.0, 1.0));
+            // The top of the circle lies at half the field of view.
+            assert_direction(projection.direction(0.5, 0.0, 1.0, 180.0), (0.0, 1.0, 0.0));
+            assert!(projection.direction(0.0, 0.0, 1.0, 180.0).is_none());
+            assert!(projection.direction(0.99, 0.5, 2.0, 180.0).is_none());
+        }
+    }
+
+    #[test]
+    fn cubemap_face_centers_map_to_their_axes() {
+        let projection = Projection::Cubemap;
+        let faces = [
+            ((1.0 / 6.0, 0.25), (1.0, 0.0, 0.0)),
+            ((0.5, 0.25), (-1.0, 0.0, 0.0)),
+            ((5.0 / 6.0, 0.25), (0.0, 1.0, 0.0)),
+            ((1.0 / 6.0, 0.75), (0.0, -1.0, 0.0)),
+            ((0.5, 0.75), (0.0, 0.0, 1.0)),
+            ((5.0 / 6.0, 0.75), (0.0, 0.0, -1.0)),
+        ];
+        for ((x, y), axis) in faces {
+            assert_direction(projection.direction(x, y, 1.5, 90.0), axis);
+        }
+    }
+
+    #[test]
+    fn perspective_and_orthographic_are_not_direction_based() {
+        assert!(Projection::Perspective
+            .direction(0.5, 0.5, 1.0, 90.0)
+            .is_none());
+        assert!(Projection::Orthographic
+            .direction(0.5, 0.5, 1.0, 90.0)
+            .is_none());
+    }
+}
@@ -27,6 +27,6 @@ impl Ray {
     }
 
     pub fn at(&self, t: f64) -> Point {
-        &self.origin + t * &self.direction
+        self.origin + t * self.direction
     }
 }
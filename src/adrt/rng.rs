@@ -0,0 +1,75 @@
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+/// Small and fast PCG32 random number generator (O'Neill, "PCG: A Family of
+/// Simple Fast Space-Efficient Statistically Good Algorithms for Random
+/// Number Generation").
+///
+/// Generators with the same seed and stream produce the same sequence, and
+/// different streams give independent sequences for the same seed.
+#[derive(Debug, Clone, Copy)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state
+            .wrapping_mul(MULTIPLIER)
+            .wrapping_add(self.increment);
+
+        let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xor_shifted.rotate_right(rotation)
+    }
+
+    /// Returns a uniformly distributed number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        self.next_u32() as f64 * (1.0 / 4_294_967_296.0)
+    }
+}
+
+/// Combines a seed with a value, e.g. a pixel or sample index, into a new
+/// seed using the SplitMix64 finalizer, so that neighbouring values give
+/// unrelated sequences.
+pub fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut z = seed ^ value.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_pcg32_reference_sequence() {
+        // Output of the demo program of the PCG reference implementation
+        // for the seed 42 and stream 54.
+        let mut rng = Pcg32::new(42, 54);
+        let expected = [
+            0xa15c_02b7,
+            0x7b47_f409,
+            0xba1d_3330,
+            0x83d2_f293,
+            0xbfa4_784b,
+            0xcbed_606e,
+        ];
+        for value in expected {
+            assert_eq!(rng.next_u32(), value);
+        }
+    }
+}
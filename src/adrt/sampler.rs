@@ -0,0 +1,440 @@
+use super::rng::{mix_seed, Pcg32};
+
+/// First primes, the bases of the Halton sequence dimensions.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// Largest `f64` below one, samples are clamped to it so they stay in `[0, 1)`.
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+/// Source of the sample values of a path. For every sample of a pixel it
+/// hands out a sequence of dimensions, which the camera and the materials
+/// consume in a fixed order: first the pixel position, then the lens
+/// position, then the scattering decisions bounce by bounce.
+///
+/// Samplers that distribute the values of one dimension well across the
+/// samples of a pixel converge faster than independent random numbers.
+pub trait Sampler {
+    /// Starts sample `sample_index` of the pixel `(i, j)` at its first
+    /// dimension.
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32);
+
+    /// Returns the next dimension as a value in `[0, 1)`.
+    fn get_1d(&mut self) -> f64;
+
+    /// Returns the next two dimensions as a point in `[0, 1)^2`.
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// Selects the sampler `Camera` renders with.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum SamplerType {
+    /// Independent uniform random numbers.
+    Independent,
+    /// Jittered strata, in a random order per pixel and dimension.
+    Stratified,
+    /// Halton sequence with Owen scrambled digits.
+    Halton,
+    /// Owen scrambled Sobol sequence. Every dimension pair is a separately
+    /// shuffled copy of the first two Sobol dimensions. Works best with a
+    /// power of two samples per pixel.
+    Sobol,
+}
+
+impl SamplerType {
+    pub fn create(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u64,
+    rng: Pcg32,
+}
+
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u64,
+    rng: Pcg32,
+}
+
+pub struct SobolSampler {
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32) {
+        self.rng = Pcg32::new(
+            mix_seed(self.seed, sample_index as u64),
+            pixel_hash(0, pixel),
+        );
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.next_f64(), self.rng.next_f64())
+    }
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel_hash: 0,
+            sample_index: 0,
+            dimension: 0,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+
+    // Returns the stratum out of `count` that the current sample falls into for the current
+    // dimension. Samples beyond `count` start over with a new order.
+    fn stratum(&self, count: u32) -> u32 {
+        let round = self.sample_index / count;
+        let hash = mix_seed(mix_seed(self.pixel_hash, self.dimension), round as u64);
+        permutation_element(self.sample_index % count, count, hash as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32) {
+        self.pixel_hash = pixel_hash(self.seed, pixel);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(
+            mix_seed(self.seed, sample_index as u64),
+            pixel_hash(0, pixel),
+        );
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        self.dimension += 1;
+        (stratum as f64 + self.rng.next_f64()) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // Use a grid that is as square as possible with at least one stratum
+        // per sample.
+        let columns = (self.samples_per_pixel as f64).sqrt().ceil() as u32;
+        let rows = self.samples_per_pixel.div_ceil(columns);
+        let stratum = self.stratum(columns * rows);
+        self.dimension += 2;
+
+        (
+            ((stratum % columns) as f64 + self.rng.next_f64()) / columns as f64,
+            ((stratum / columns) as f64 + self.rng.next_f64()) / rows as f64,
+        )
+    }
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_hash: 0,
+            sample_index: 0,
+            dimension: 0,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32) {
+        self.pixel_hash = pixel_hash(self.seed, pixel);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(
+            mix_seed(self.seed, sample_index as u64),
+            pixel_hash(0, pixel),
+        );
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        // Fall back to random numbers once the bases run out.
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => owen_scrambled_radical_inverse(
+                base,
+                self.sample_index as u64,
+                mix_seed(self.pixel_hash, dimension),
+            ),
+            None => self.rng.next_f64(),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_hash: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Shuffles the sample index with a scramble that is unique to the current dimension, so
+    // that the dimensions are decorrelated from each other.
+    fn shuffled_index(&self, hash: u64) -> u32 {
+        nested_uniform_scramble(self.sample_index, hash as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32) {
+        self.pixel_hash = pixel_hash(self.seed, pixel);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = mix_seed(self.pixel_hash, self.dimension);
+        self.dimension += 1;
+
+        let index = self.shuffled_index(hash);
+        to_unit_float(nested_uniform_scramble(
+            sobol(index, 0),
+            (hash >> 32) as u32,
+        ))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = mix_seed(self.pixel_hash, self.dimension);
+        self.dimension += 2;
+
+        let index = self.shuffled_index(hash);
+        let hash_y = mix_seed(hash, 1);
+        (
+            to_unit_float(nested_uniform_scramble(
+                sobol(index, 0),
+                (hash >> 32) as u32,
+            )),
+            to_unit_float(nested_uniform_scramble(sobol(index, 1), hash_y as u32)),
+        )
+    }
+}
+
+fn pixel_hash(seed: u64, (i, j): (i32, i32)) -> u64 {
+    mix_seed(mix_seed(seed, i as u32 as u64), j as u32 as u64)
+}
+
+fn to_unit_float(value: u32) -> f64 {
+    (value as f64 * (1.0 / 4_294_967_296.0)).min(ONE_MINUS_EPSILON)
+}
+
+/// Returns element `index` of a random permutation of `0..count` selected by
+/// `hash`, without storing the permutation (Kensler, "Correlated
+/// Multi-Jittered Sampling").
+fn permutation_element(mut index: u32, count: u32, hash: u32) -> u32 {
+    let mut mask = count.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // Cycle walk until the permuted index falls into range.
+    loop {
+        index ^= hash;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= hash >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= hash >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= hash >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | hash >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < count {
+            break;
+        }
+    }
+
+    (index.wrapping_add(hash)) % count
+}
+
+/// Radical inverse of `index` in `base` with every digit permuted depending
+/// on the digits before it, which is Owen scrambling.
+fn owen_scrambled_radical_inverse(base: u32, mut index: u64, hash: u64) -> f64 {
+    let base = base as u64;
+    let inverse_base = 1.0 / base as f64;
+    let mut inverse_base_m = 1.0;
+    let mut reversed_digits: u64 = 0;
+
+    // Continue past the last non-zero digit of the index, as the leading
+    // zeros get scrambled too, until the digits fall below the precision.
+    while 1.0 - (base - 1) as f64 * inverse_base_m < 1.0 {
+        let next = index / base;
+        let digit = (index - next * base) as u32;
+        let digit_hash = mix_seed(hash, reversed_digits) as u32;
+        let digit = permutation_element(digit, base as u32, digit_hash) as u64;
+        reversed_digits = reversed_digits.wrapping_mul(base).wrapping_add(digit);
+        inverse_base_m *= inverse_base;
+        index = next;
+    }
+
+    (inverse_base_m * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+}
+
+/// Returns dimension `dimension` (0 or 1) of the Sobol point `index` as a
+/// 32 bit fixed point number.
+fn sobol(index: u32, dimension: u32) -> u32 {
+    // The first dimension is the van der Corput sequence, the second one is
+    // generated by the primitive polynomial x + 1.
+    let mut direction: u32 = 1 << 31;
+    let mut result = 0;
+    let mut index = index;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        direction = if dimension == 0 {
+            direction >> 1
+        } else {
+            direction ^ (direction >> 1)
+        };
+        index >>= 1;
+    }
+    result
+}
+
+/// Owen scrambles the bits of `value` (Burley, "Practical Hash-based Owen
+/// Scrambling").
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    // RMS error of the per pixel estimates of an integral over [0, 1)^4.
+    fn rms_error(
+        sampler_type: SamplerType,
+        samples: u32,
+        integrand: fn(&mut dyn Sampler) -> f64,
+        expected: f64,
+    ) -> f64 {
+        let pixel_count = 1024;
+        let mut sampler = sampler_type.create(samples, 0);
+        let mut squared_error = 0.0;
+        for i in 0..pixel_count {
+            let mut sum = 0.0;
+            for sample in 0..samples {
+                sampler.start_pixel_sample((i, 0), sample);
+                sum += integrand(sampler.as_mut());
+            }
+            let error = sum / samples as f64 - expected;
+            squared_error += error * error;
+        }
+        f64::sqrt(squared_error / pixel_count as f64)
+    }
+
+    fn smooth_4d(sampler: &mut dyn Sampler) -> f64 {
+        let (x, y) = sampler.get_2d();
+        let (z, w) = sampler.get_2d();
+        f64::sin(PI * x) * f64::sin(PI * y) * (z + w)
+    }
+
+    #[test]
+    fn samples_stay_in_the_unit_interval() {
+        for sampler_type in [
+            SamplerType::Independent,
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+        ] {
+            let mut sampler = sampler_type.create(16, 3);
+            for sample in 0..16 {
+                sampler.start_pixel_sample((5, 7), sample);
+                for _ in 0..40 {
+                    let value = sampler.get_1d();
+                    assert!((0.0..1.0).contains(&value), "{:?}", sampler_type);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_converge_faster() {
+        let expected = 4.0 / (PI * PI);
+        for samples in [16, 64] {
+            let independent = rms_error(SamplerType::Independent, samples, smooth_4d, expected);
+            for sampler_type in [
+                SamplerType::Stratified,
+                SamplerType::Halton,
+                SamplerType::Sobol,
+            ] {
+                let error = rms_error(sampler_type, samples, smooth_4d, expected);
+                assert!(
+                    error < independent,
+                    "{:?} at {} spp: {} is not below {}",
+                    sampler_type,
+                    samples,
+                    error,
+                    independent
+                );
+            }
+        }
+    }
+}
@@ -1,31 +1,36 @@
 use super::{
+    aabb::Aabb,
+    checkpoint::Fingerprint,
     hittable::{HitRecord, Hittable},
     interval::Interval,
     material::MaterialType,
     ray::Ray,
     utility::Point,
-    vec3::dot_product,
+    vec3::{dot_product, Vec3},
 };
 
 pub struct Sphere {
     center: Point,
     radius: f64,
     material: MaterialType,
+    bbox: Aabb,
 }
 
 impl Sphere {
     #[allow(dead_code)]
     pub fn from(center: Point, radius: f64, material: MaterialType) -> Sphere {
+        let radius_vec = Vec3::from(radius, radius, radius);
         Sphere {
             center,
             radius,
             material,
+            bbox: Aabb::from_points(center - radius_vec, center + radius_vec),
         }
     }
 }
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, ray_t: &Interval, record: &mut HitRecord) -> bool {
+impl Sphere {
+    fn nearest_root(&self, ray: &Ray, ray_t: &Interval) -> Option<f64> {
         let oc = ray.origin() - self.center;
         let a = ray.direction().length_squared();
         let half_b = dot_product(&oc, ray.direction());
@@ -33,7 +38,7 @@ impl Hittable for Sphere {
 
         let discriminant = half_b * half_b - a * c;
         if discriminant < 0.0 {
-            return false;
+            return None;
         }
 
         let sqrt_disc = f64::sqrt(discriminant);
@@ -42,10 +47,21 @@ impl Hittable for Sphere {
         if !ray_t.surrounds(root) {
             root = (-half_b + sqrt_disc) / a;
             if !ray_t.surrounds(root) {
-                return false;
+                return None;
             }
         }
 
+        Some(root)
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, ray_t: &Interval, record: &mut HitRecord) -> bool {
+        let root = match self.nearest_root(ray, ray_t) {
+            Some(root) => root,
+            None => return false,
+        };
+
         record.t = root;
         record.pt = ray.at(record.t);
         let outward_normal = (record.pt - self.center) / self.radius;
@@ -54,4 +70,19 @@ impl Hittable for Sphere {
 
         true
     }
+
+    fn occluded(&self, ray: &Ray, ray_t: &Interval) -> bool {
+        self.nearest_root(ray, ray_t).is_some()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn fingerprint(&self, fingerprint: &mut Fingerprint) {
+        fingerprint.write_str("sphere");
+        fingerprint.write_vec3(&self.center);
+        fingerprint.write_f64(self.radius);
+        fingerprint.write_str(&format!("{:?}", self.material));
+    }
 }
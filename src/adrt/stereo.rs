@@ -0,0 +1,126 @@
+/// Arrangement of the two eye views in the combined output image.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum StereoLayout {
+    /// Left eye in the left half, right eye in the right half.
+    SideBySide,
+    /// Left eye in the top half, right eye in the bottom half.
+    TopBottom,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// Stereo camera rig. Each eye is rendered with the camera's image size, so
+/// the combined image is twice as wide or twice as high.
+#[derive(Debug, Clone, Copy)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    /// Distance between the two eyes in world units.
+    pub interpupillary_distance: f64,
+    /// Distance of the zero parallax plane from the camera. Objects at this
+    /// distance appear at the screen, closer ones in front of it.
+    pub convergence_distance: f64,
+    /// Omni-directional stereo: instead of shifting both eyes sideways once,
+    /// every ray starts on a circle with the interpupillary distance as its
+    /// diameter, tangent to the ray's horizontal direction. Use this with
+    /// `Projection::Equirectangular` to get correct stereo in every viewing
+    /// direction of a 360° panorama.
+    pub omnidirectional: bool,
+}
+
+#[allow(dead_code)]
+impl Stereo {
+    pub fn from(
+        layout: StereoLayout,
+        interpupillary_distance: f64,
+        convergence_distance: f64,
+    ) -> Self {
+        Self {
+            layout,
+            interpupillary_distance,
+            convergence_distance,
+            omnidirectional: false,
+        }
+    }
+
+    pub fn omnidirectional(interpupillary_distance: f64, convergence_distance: f64) -> Self {
+        Self {
+            layout: StereoLayout::TopBottom,
+            interpupillary_distance,
+            convergence_distance,
+            omnidirectional: true,
+        }
+    }
+
+    /// Returns the size of the combined image for the given per-eye size.
+    pub fn output_size(&self, eye_width: i32, eye_height: i32) -> (i32, i32) {
+        match self.layout {
+            StereoLayout::SideBySide => (2 * eye_width, eye_height),
+            StereoLayout::TopBottom => (eye_width, 2 * eye_height),
+        }
+    }
+
+    /// Maps a pixel of the combined image to the eye and its pixel location
+    /// within that eye's view.
+    pub fn eye_pixel(&self, i: i32, j: i32, eye_width: i32, eye_height: i32) -> (Eye, i32, i32) {
+        match self.layout {
+            StereoLayout::SideBySide if i >= eye_width => (Eye::Right, i - eye_width, j),
+            StereoLayout::TopBottom if j >= eye_height => (Eye::Right, i, j - eye_height),
+            _ => (Eye::Left, i, j),
+        }
+    }
+}
+
+impl Eye {
+    /// Returns -1 for the left and 1 for the right eye.
+    pub fn sign(&self) -> f64 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eye_pixels(stereo: &Stereo) -> Vec<(f64, i32, i32)> {
+        let (width, height) = stereo.output_size(3, 2);
+        (0..height)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let (eye, x, y) = stereo.eye_pixel(i, j, 3, 2);
+                (eye.sign(), x, y)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn side_by_side_puts_the_left_eye_on_the_left() {
+        let stereo = Stereo::from(StereoLayout::SideBySide, 0.064, 2.0);
+        assert_eq!(stereo.output_size(3, 2), (6, 2));
+        #[rustfmt::skip]
+        assert_eq!(eye_pixels(&stereo), [
+            (-1.0, 0, 0), (-1.0, 1, 0), (-1.0, 2, 0), (1.0, 0, 0), (1.0, 1, 0), (1.0, 2, 0),
+            (-1.0, 0, 1), (-1.0, 1, 1), (-1.0, 2, 1), (1.0, 0, 1), (1.0, 1, 1), (1.0, 2, 1),
+        ]);
+    }
+
+    #[test]
+    fn top_bottom_puts_the_left_eye_on_top() {
+        let stereo = Stereo::from(StereoLayout::TopBottom, 0.064, 2.0);
+        assert_eq!(stereo.output_size(3, 2), (3, 4));
+        #[rustfmt::skip]
+        assert_eq!(eye_pixels(&stereo), [
+            (-1.0, 0, 0), (-1.0, 1, 0), (-1.0, 2, 0),
+            (-1.0, 0, 1), (-1.0, 1, 1), (-1.0, 2, 1),
+            (1.0, 0, 0), (1.0, 1, 0), (1.0, 2, 0),
+            (1.0, 0, 1), (1.0, 1, 1), (1.0, 2, 1),
+        ]);
+    }
+}
@@ -0,0 +1,693 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::{
+    error::RenderError,
+    tile::Tile,
+    utility::{write_color, Color},
+};
+
+/// Side length of the tiles of TIFF files, which must be a multiple of 16.
+const TIFF_TILE_SIZE: usize = 256;
+/// Largest amount of data a stored deflate block can hold.
+const MAX_STORED_BLOCK: usize = 65535;
+
+/// Writes an image to disk piece by piece while it is rendered, so that
+/// images larger than the memory can be rendered with
+/// `Camera::render_streamed`.
+pub trait StreamWriter {
+    /// Side length of the tiles the writer takes. `None` means the writer
+    /// takes blocks of whole rows, from the top to the bottom.
+    fn tile_size(&self) -> Option<usize>;
+
+    /// Called once with the image size before the first block.
+    fn start(&mut self, width: usize, height: usize) -> Result<(), RenderError>;
+
+    /// Writes a finished block of linear pixels, stored row by row. Blocks
+    /// of tile writers start on the tile grid and are cut at the image
+    /// border.
+    fn write_block(&mut self, block: &Tile, pixels: &[Color]) -> Result<(), RenderError>;
+
+    /// Called once after the last block.
+    fn finish(&mut self) -> Result<(), RenderError>;
+}
+
+/// Binary 8-bit PPM, written row by row.
+pub struct PpmStreamWriter {
+    file_path: String,
+    file: BufWriter<File>,
+}
+
+/// 8-bit RGB PNG, written row by row. The rows are stored without
+/// compression, which needs no memory beyond the current rows.
+pub struct PngStreamWriter {
+    file_path: String,
+    file: BufWriter<File>,
+    // Running checksum of the uncompressed data, which ends the zlib stream
+    adler: (u32, u32),
+    zlib_header_written: bool,
+}
+
+/// Uncompressed, tiled 8-bit RGB TIFF. Every tile has a fixed place in the
+/// file, so tiles are written in any order as soon as they are finished.
+/// Files above 4 GB are written as BigTIFF.
+pub struct TiffStreamWriter {
+    file_path: String,
+    file: File,
+    width: usize,
+    height: usize,
+    data_offset: u64,
+}
+
+/// Creates the writer for the format given by the extension of the path:
+/// `.ppm`, `.png`, `.tif` or `.tiff`.
+#[allow(dead_code)]
+pub fn create(file_path: &str) -> Result<Box<dyn StreamWriter>, RenderError> {
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("ppm") => Ok(Box::new(PpmStreamWriter::create(file_path)?)),
+        Some("png") => Ok(Box::new(PngStreamWriter::create(file_path)?)),
+        Some("tif") | Some("tiff") => Ok(Box::new(TiffStreamWriter::create(file_path)?)),
+        _ => Err(RenderError::UnsupportedFormat {
+            path: file_path.to_string(),
+        }),
+    }
+}
+
+impl PpmStreamWriter {
+    pub fn create(file_path: &str) -> Result<Self, RenderError> {
+        let file = File::create(file_path).map_err(RenderError::io(file_path))?;
+        Ok(Self {
+            file_path: file_path.to_string(),
+            file: BufWriter::new(file),
+        })
+    }
+}
+
+impl StreamWriter for PpmStreamWriter {
+    fn tile_size(&self) -> Option<usize> {
+        None
+    }
+
+    fn start(&mut self, width: usize, height: usize) -> Result<(), RenderError> {
+        self.file
+            .write_all(format!("P6\n{} {} 255\n", width, height).as_bytes())
+            .map_err(RenderError::io(&self.file_path))
+    }
+
+    fn write_block(&mut self, _block: &Tile, pixels: &[Color]) -> Result<(), RenderError> {
+        for pixel in pixels {
+            write_color(&mut self.file, *pixel).map_err(RenderError::io(&self.file_path))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), RenderError> {
+        self.file.flush().map_err(RenderError::io(&self.file_path))
+    }
+}
+
+impl PngStreamWriter {
+    pub fn create(file_path: &str) -> Result<Self, RenderError> {
+        let file = File::create(file_path).map_err(RenderError::io(file_path))?;
+        Ok(Self {
+            file_path: file_path.to_string(),
+            file: BufWriter::new(file),
+            adler: (1, 0),
+            zlib_header_written: false,
+        })
+    }
+
+    fn write_chunk(&mut self, kind: &[u8; 4], data: &[u8]) -> Result<(), RenderError> {
+        let mut crc = Crc32::new();
+        crc.update(kind);
+        crc.update(data);
+
+        let write = |file: &mut BufWriter<File>| -> std::io::Result<()> {
+            file.write_all(&(data.len() as u32).to_be_bytes())?;
+            file.write_all(kind)?;
+            file.write_all(data)?;
+            file.write_all(&crc.finish().to_be_bytes())
+        };
+        write(&mut self.file).map_err(RenderError::io(&self.file_path))
+    }
+}
+
+impl StreamWriter for PngStreamWriter {
+    fn tile_size(&self) -> Option<usize> {
+        None
+    }
+
+    fn start(&mut self, width: usize, height: usize) -> Result<(), RenderError> {
+        self.file
+            .write_all(b"\x89PNG\r\n\x1a\n")
+            .map_err(RenderError::io(&self.file_path))?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, deflate, adaptive filtering, no interlacing
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        self.write_chunk(b"IHDR", &header)
+    }
+
+    fn write_block(&mut self, block: &Tile, pixels: &[Color]) -> Result<(), RenderError> {
+        // Every row starts with its filter type, zero being no filter.
+        let mut raw = Vec::with_capacity(pixels.len() * 3 + block.height as usize);
+        for row in pixels.chunks((block.width as usize).max(1)) {
+            raw.push(0);
+            for pixel in row {
+                write_color(&mut raw, *pixel).map_err(RenderError::io(&self.file_path))?;
+            }
+        }
+
+        let (mut a, mut b) = self.adler;
+        for byte in raw.iter() {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        self.adler = (a, b);
+
+        let mut data = Vec::with_capacity(raw.len() + raw.len() / MAX_STORED_BLOCK * 5 + 7);
+        if !self.zlib_header_written {
+            data.extend_from_slice(&[0x78, 0x01]);
+            self.zlib_header_written = true;
+        }
+        for block in raw.chunks(MAX_STORED_BLOCK) {
+            // Stored block that is not the last one, followed by its length and
+            // the complement of the length.
+            data.push(0);
+            data.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            data.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            data.extend_from_slice(block);
+        }
+        self.write_chunk(b"IDAT", &data)
+    }
+
+    fn finish(&mut self) -> Result<(), RenderError> {
+        // An empty final block ends the deflate stream, the checksum the zlib
+        // stream.
+        let mut data = Vec::with_capacity(11);
+        if !self.zlib_header_written {
+            data.extend_from_slice(&[0x78, 0x01]);
+            self.zlib_header_written = true;
+        }
+        data.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        let (a, b) = self.adler;
+        data.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        self.write_chunk(b"IDAT", &data)?;
+        self.write_chunk(b"IEND", &[])?;
+
+        self.file.flush().map_err(RenderError::io(&self.file_path))
+    }
+}
+
+impl TiffStreamWriter {
+    pub fn create(file_path: &str) -> Result<Self, RenderError> {
+        let file = File::create(file_path).map_err(RenderError::io(file_path))?;
+        Ok(Self {
+            file_path: file_path.to_string(),
+            file,
+            width: 0,
+            height: 0,
+            data_offset: 0,
+        })
+    }
+
+    fn tile_bytes() -> u64 {
+        (TIFF_TILE_SIZE * TIFF_TILE_SIZE * 3) as u64
+    }
+
+    // Builds the header and the directory of the file, with the tiles stored one after
+    // another behind it. Returns the bytes and the offset of the first tile.
+    fn header(&self, big: bool) -> (Vec<u8>, u64) {
+        let columns = self.width.div_ceil(TIFF_TILE_SIZE);
+        let rows = self.height.div_ceil(TIFF_TILE_SIZE);
+        let tile_count = columns * rows;
+
+        let (offset_size, offset_type) = if big { (8, 16) } else { (4, 4) };
+        let encode_offsets = |values: &mut dyn Iterator<Item = u64>| -> Vec<u8> {
+            values
+                .flat_map(|value| {
+                    if big {
+                        value.to_le_bytes().to_vec()
+                    } else {
+                        (value as u32).to_le_bytes().to_vec()
+                    }
+                })
+                .collect()
+        };
+        let short = |value: u16| value.to_le_bytes().to_vec();
+        let long = |value: u32| value.to_le_bytes().to_vec();
+
+        // Tag, type, count and values, sorted by tag. Types are 3 for 16-bit,
+        // 4 for 32-bit and 16 for 64-bit integers.
+        let mut entries: Vec<(u16, u16, usize, Vec<u8>)> = vec![
+            (256, 4, 1, long(self.width as u32)),
+            (257, 4, 1, long(self.height as u32)),
+            (258, 3, 3, [short(8), short(8), short(8)].concat()),
+            // No compression, RGB, chunky planar configuration
+            (259, 3, 1, short(1)),
+            (262, 3, 1, short(2)),
+            (277, 3, 1, short(3)),
+            (284, 3, 1, short(1)),
+            (322, 4, 1, long(TIFF_TILE_SIZE as u32)),
+            (323, 4, 1, long(TIFF_TILE_SIZE as u32)),
+            (
+                324,
+                offset_type,
+                tile_count,
+                vec![0; tile_count * offset_size],
+            ),
+            (
+                325,
+                offset_type,
+                tile_count,
+                encode_offsets(&mut (0..tile_count).map(|_| Self::tile_bytes())),
+            ),
+        ];
+
+        let (header_size, count_size, entry_size, inline_size, next_size) = if big {
+            (16, 8, 20, 8, 8)
+        } else {
+            (8, 2, 12, 4, 4)
+        };
+        let directory_size = count_size + entries.len() * entry_size + next_size;
+        let external_size: usize = entries
+            .iter()
+            .filter(|entry| entry.3.len() > inline_size)
+            .map(|entry| entry.3.len())
+            .sum();
+        let data_offset = (header_size + directory_size + external_size).next_multiple_of(8) as u64;
+        entries[9].3 = encode_offsets(
+            &mut (0..tile_count as u64).map(|index| data_offset + index * Self::tile_bytes()),
+        );
+
+        let mut bytes = Vec::with_capacity(data_offset as usize);
+        let mut external = Vec::with_capacity(external_size);
+        let mut external_offset = (header_size + directory_size) as u64;
+        if big {
+            bytes.extend_from_slice(b"II");
+            bytes.extend_from_slice(&43u16.to_le_bytes());
+            bytes.extend_from_slice(&8u16.to_le_bytes());
+            bytes.extend_from_slice(&0u16.to_le_bytes());
+            bytes.extend_from_slice(&(header_size as u64).to_le_bytes());
+            bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        } else {
+            bytes.extend_from_slice(b"II");
+            bytes.extend_from_slice(&42u16.to_le_bytes());
+            bytes.extend_from_slice(&(header_size as u32).to_le_bytes());
+            bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        }
+        for (tag, kind, count, values) in entries.iter() {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&kind.to_le_bytes());
+            if big {
+                bytes.extend_from_slice(&(*count as u64).to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&(*count as u32).to_le_bytes());
+            }
+
+            // Values that don't fit into the entry are stored behind the directory.
+            let mut value = if values.len() > inline_size {
+                let offset = external_offset;
+                external_offset += values.len() as u64;
+                external.extend_from_slice(values);
+                encode_offsets(&mut std::iter::once(offset))
+            } else {
+                values.clone()
+            };
+            value.resize(inline_size, 0);
+            bytes.extend_from_slice(&value);
+        }
+        bytes.extend_from_slice(&vec![0; next_size]);
+        bytes.extend_from_slice(&external);
+        bytes.resize(data_offset as usize, 0);
+
+        (bytes, data_offset)
+    }
+}
+
+impl StreamWriter for TiffStreamWriter {
+    fn tile_size(&self) -> Option<usize> {
+        Some(TIFF_TILE_SIZE)
+    }
+
+    fn start(&mut self, width: usize, height: usize) -> Result<(), RenderError> {
+        self.width = width;
+        self.height = height;
+
+        let (mut header, mut data_offset) = self.header(false);
+        let tile_count = width.div_ceil(TIFF_TILE_SIZE) * height.div_ceil(TIFF_TILE_SIZE);
+        if data_offset + tile_count as u64 * Self::tile_bytes() > u32::MAX as u64 {
+            (header, data_offset) = self.header(true);
+        }
+        self.data_offset = data_offset;
+
+        let write = |file: &mut File| -> std::io::Result<()> {
+            file.write_all(&header)?;
+            // Reserve the space of all tiles, so that missing tiles read as black.
+            file.set_len(data_offset + tile_count as u64 * Self::tile_bytes())
+        };
+        write(&mut self.file).map_err(RenderError::io(&self.file_path))
+    }
+
+    fn write_block(&mut self, block: &Tile, pixels: &[Color]) -> Result<(), RenderError> {
+        // Tiles at the right and bottom border are padded to the full tile size.
+        let mut data = vec![0; Self::tile_bytes() as usize];
+        let mut row_bytes = Vec::with_capacity(block.width as usize * 3);
+        for (row, block_row) in pixels.chunks((block.width as usize).max(1)).enumerate() {
+            row_bytes.clear();
+            for pixel in block_row {
+                write_color(&mut row_bytes, *pixel).map_err(RenderError::io(&self.file_path))?;
+            }
+            let start = row * TIFF_TILE_SIZE * 3;
+            data[start..start + row_bytes.len()].copy_from_slice(&row_bytes);
+        }
+
+        let columns = self.width.div_ceil(TIFF_TILE_SIZE);
+        let index = block.y as usize / TIFF_TILE_SIZE * columns + block.x as usize / TIFF_TILE_SIZE;
+        let offset = self.data_offset + index as u64 * Self::tile_bytes();
+
+        let write = |file: &mut File| -> std::io::Result<()> {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&data)
+        };
+        write(&mut self.file).map_err(RenderError::io(&self.file_path))
+    }
+
+    fn finish(&mut self) -> Result<(), RenderError> {
+        self.file.flush().map_err(RenderError::io(&self.file_path))
+    }
+}
+
+/// Lookup table of the CRC-32 for every byte value.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// CRC-32 as used by PNG chunks.
+struct Crc32(u32);
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(0xffff_ffff)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = CRC_TABLE[((self.0 ^ *byte as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, fs};
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rust_tracer_{}_{}", std::process::id(), name))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn gradient(width: usize, height: usize) -> Vec<Color> {
+        (0..width * height)
+            .map(|index| {
+                let (x, y) = ((index % width) as f64, (index / width) as f64);
+                Color::from(x / width as f64, y / height as f64, 0.5)
+            })
+            .collect()
+    }
+
+    fn encoded(pixels: &[Color]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for pixel in pixels {
+            write_color(&mut bytes, *pixel).unwrap();
+        }
+        bytes
+    }
+
+    fn be_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes(bytes[..4].try_into().unwrap())
+    }
+
+    fn le(bytes: &[u8], size: usize) -> u64 {
+        let mut value = [0; 8];
+        value[..size].copy_from_slice(&bytes[..size]);
+        u64::from_le_bytes(value)
+    }
+
+    // Reads the directory of a little-endian TIFF or BigTIFF into the values of every tag.
+    fn tiff_tags(bytes: &[u8]) -> (bool, HashMap<u16, Vec<u64>>) {
+        assert_eq!(&bytes[..2], b"II");
+        let big = match le(&bytes[2..], 2) {
+            42 => false,
+            43 => true,
+            version => panic!("unknown TIFF version {}", version),
+        };
+        let (directory, count_size, entry_size, inline_size) = if big {
+            assert_eq!((le(&bytes[4..], 2), le(&bytes[6..], 2)), (8, 0));
+            (le(&bytes[8..], 8) as usize, 8, 20, 8)
+        } else {
+            (le(&bytes[4..], 4) as usize, 2, 12, 4)
+        };
+
+        let count = le(&bytes[directory..], count_size) as usize;
+        let mut tags = HashMap::new();
+        let mut previous_tag = 0;
+        for index in 0..count {
+            let entry = &bytes[directory + count_size + index * entry_size..];
+            let tag = le(entry, 2) as u16;
+            assert!(tag > previous_tag, "tags must be sorted");
+            previous_tag = tag;
+
+            let value_size = match le(&entry[2..], 2) {
+                3 => 2,
+                4 => 4,
+                16 => 8,
+                kind => panic!("unexpected type {} of tag {}", kind, tag),
+            };
+            let (value_count, field) = if big {
+                (le(&entry[4..], 8) as usize, &entry[12..20])
+            } else {
+                (le(&entry[4..], 4) as usize, &entry[8..12])
+            };
+            let values = if value_count * value_size <= inline_size {
+                field
+            } else {
+                &bytes[le(field, inline_size) as usize..]
+            };
+            let values = (0..value_count)
+                .map(|i| le(&values[i * value_size..], value_size))
+                .collect();
+            tags.insert(tag, values);
+        }
+        let next = directory + count_size + count * entry_size;
+        assert_eq!(le(&bytes[next..], inline_size), 0, "only one directory");
+        (big, tags)
+    }
+
+    #[test]
+    fn png_chunks_and_zlib_stream_are_valid() {
+        let (width, height) = (70, 5);
+        let pixels = gradient(width, height);
+        let file_path = temp_path("stream.png");
+        let mut writer = PngStreamWriter::create(&file_path).unwrap();
+        writer.start(width, height).unwrap();
+        for (y, rows) in [(0, 2), (2, 3)] {
+            let block = Tile {
+                x: 0,
+                y,
+                width: width as i32,
+                height: rows,
+            };
+            let start = y as usize * width;
+            let end = start + rows as usize * width;
+            writer.write_block(&block, &pixels[start..end]).unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = fs::read(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut position = 8;
+        while position < bytes.len() {
+            let length = be_u32(&bytes[position..]) as usize;
+            let kind = &bytes[position + 4..position + 8];
+            let data = &bytes[position + 8..position + 8 + length];
+            let mut crc = Crc32::new();
+            crc.update(&bytes[position + 4..position + 8 + length]);
+            assert_eq!(crc.finish(), be_u32(&bytes[position + 8 + length..]));
+            chunks.push((kind.to_vec(), data.to_vec()));
+            position += 12 + length;
+        }
+        assert_eq!(position, bytes.len());
+        // The CRC of an empty IEND chunk is fixed by the specification.
+        assert_eq!(&bytes[bytes.len() - 4..], [0xae, 0x42, 0x60, 0x82]);
+
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| kind.as_slice()).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IDAT", b"IDAT", b"IEND"]);
+        let header = &chunks[0].1;
+        assert_eq!(be_u32(header), width as u32);
+        assert_eq!(be_u32(&header[4..]), height as u32);
+        assert_eq!(&header[8..], [8, 2, 0, 0, 0]);
+
+        let zlib: Vec<u8> = chunks[1..4]
+            .iter()
+            .flat_map(|(_, data)| data.clone())
+            .collect();
+        assert_eq!(zlib[0] & 0x0f, 8, "deflate compression");
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0);
+        let mut raw = Vec::new();
+        let mut position = 2;
+        loop {
+            let final_block = zlib[position] & 1 == 1;
+            assert_eq!(zlib[position] >> 1, 0, "stored block");
+            let length = le(&zlib[position + 1..], 2) as u16;
+            assert_eq!(le(&zlib[position + 3..], 2) as u16, !length);
+            position += 5;
+            raw.extend_from_slice(&zlib[position..position + length as usize]);
+            position += length as usize;
+            if final_block {
+                break;
+            }
+        }
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in raw.iter() {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(be_u32(&zlib[position..]), (b << 16) | a);
+        assert_eq!(position + 4, zlib.len());
+
+        for (row, scanline) in raw.chunks(width * 3 + 1).enumerate() {
+            assert_eq!(scanline[0], 0, "no filter");
+            assert_eq!(
+                &scanline[1..],
+                encoded(&pixels[row * width..(row + 1) * width])
+            );
+        }
+        assert_eq!(raw.len(), height * (width * 3 + 1));
+    }
+
+    #[test]
+    fn tiff_directory_points_at_the_tiles() {
+        let (width, height) = (300, 260);
+        let pixels = gradient(width, height);
+        let file_path = temp_path("stream.tif");
+        let mut writer = TiffStreamWriter::create(&file_path).unwrap();
+        writer.start(width, height).unwrap();
+        // Tiles may arrive in any order.
+        for (x, y) in [(256, 256), (0, 256), (256, 0), (0, 0)] {
+            let block = Tile {
+                x,
+                y,
+                width: (width as i32 - x).min(256),
+                height: (height as i32 - y).min(256),
+            };
+            let block_pixels: Vec<Color> = (y..y + block.height)
+                .flat_map(|row| {
+                    let start = row as usize * width + x as usize;
+                    pixels[start..start + block.width as usize].to_vec()
+                })
+                .collect();
+            writer.write_block(&block, &block_pixels).unwrap();
+        }
+        writer.finish().unwrap();
+        let bytes = fs::read(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        let (big, tags) = tiff_tags(&bytes);
+        assert!(!big);
+        assert_eq!(tags[&256], [300]);
+        assert_eq!(tags[&257], [260]);
+        assert_eq!(tags[&258], [8, 8, 8]);
+        assert_eq!(tags[&259], [1]);
+        assert_eq!(tags[&262], [2]);
+        assert_eq!(tags[&277], [3]);
+        assert_eq!(tags[&284], [1]);
+        assert_eq!(tags[&322], [256]);
+        assert_eq!(tags[&323], [256]);
+
+        let tile_bytes = 256 * 256 * 3;
+        let offsets = &tags[&324];
+        assert_eq!(tags[&325], [tile_bytes; 4]);
+        assert_eq!(offsets.len(), 4);
+        assert_eq!(offsets[0] % 8, 0);
+        for (index, offset) in offsets.iter().enumerate() {
+            assert_eq!(*offset, offsets[0] + index as u64 * tile_bytes);
+        }
+        assert_eq!(bytes.len() as u64, offsets[3] + tile_bytes);
+
+        // Check a row of every tile, including the cut ones at the border.
+        for (tile, (x, y)) in [(0, 0), (256, 0), (0, 256), (256, 256)].iter().enumerate() {
+            let row = 3;
+            let tile_width = (width - x).min(256);
+            let start = offsets[tile] as usize + row * 256 * 3;
+            let image_start = (y + row) * width + x;
+            assert_eq!(
+                &bytes[start..start + tile_width * 3],
+                encoded(&pixels[image_start..image_start + tile_width])
+            );
+            // The padding of border tiles stays black.
+            assert!(bytes[start + tile_width * 3..start + 256 * 3]
+                .iter()
+                .all(|byte| *byte == 0));
+        }
+    }
+
+    #[test]
+    fn bigtiff_directory_uses_64_bit_offsets() {
+        let mut writer = TiffStreamWriter::create(&temp_path("big.tif")).unwrap();
+        let _ = fs::remove_file(temp_path("big.tif"));
+        writer.width = 600;
+        writer.height = 300;
+        let (bytes, data_offset) = writer.header(true);
+        assert_eq!(bytes.len() as u64, data_offset);
+
+        let (big, tags) = tiff_tags(&bytes);
+        assert!(big);
+        assert_eq!(tags[&256], [600]);
+        assert_eq!(tags[&257], [300]);
+        assert_eq!(tags[&258], [8, 8, 8]);
+        let tile_bytes = 256 * 256 * 3;
+        assert_eq!(tags[&325], [tile_bytes; 6]);
+        let expected: Vec<u64> = (0..6).map(|i| data_offset + i * tile_bytes).collect();
+        assert_eq!(tags[&324], expected);
+        assert_eq!(data_offset % 8, 0);
+    }
+}
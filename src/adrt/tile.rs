@@ -0,0 +1,243 @@
+use std::f64::consts::PI;
+
+/// Order in which the tiles of an image are rendered.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum TileOrder {
+    /// Row by row from the top left, like the scanlines of the image.
+    Scanline,
+    /// Ring by ring outwards from the center, so the center of the image is
+    /// finished first.
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles close together
+    /// and so improves cache coherence.
+    Hilbert,
+}
+
+/// Rectangular block of pixels of the output image.
+#[derive(Debug, Clone, Copy)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Part of the output image to render, e.g. to re-render a noisy area. The
+/// rays of the pixels inside are the same as in a full render, so the crop
+/// lines up with it.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum CropWindow {
+    /// Rectangle in pixels of the output image, starting at the top left.
+    Pixels {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    /// Rectangle in `[0, 1]^2` relative to the output image size, starting at
+    /// the top left.
+    Normalized {
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+    },
+}
+
+impl CropWindow {
+    /// Returns the pixels covered by the window, clipped to the image. The
+    /// region is empty if the window lies outside of the image.
+    pub fn region(&self, image_width: i32, image_height: i32) -> Tile {
+        let (x0, y0, x1, y1) = match *self {
+            CropWindow::Pixels {
+                x,
+                y,
+                width,
+                height,
+            } => (x, y, x + width, y + height),
+            // Rounding both bounds up assigns every pixel to exactly one of two
+            // adjacent windows.
+            CropWindow::Normalized {
+                x_min,
+                y_min,
+                x_max,
+                y_max,
+            } => (
+                (x_min * image_width as f64).ceil() as i32,
+                (y_min * image_height as f64).ceil() as i32,
+                (x_max * image_width as f64).ceil() as i32,
+                (y_max * image_height as f64).ceil() as i32,
+            ),
+        };
+        let x0 = x0.clamp(0, image_width);
+        let y0 = y0.clamp(0, image_height);
+
+        Tile {
+            x: x0,
+            y: y0,
+            width: x1.clamp(0, image_width).saturating_sub(x0).max(0),
+            height: y1.clamp(0, image_height).saturating_sub(y0).max(0),
+        }
+    }
+}
+
+impl TileOrder {
+    /// Splits an image into tiles of at most `tile_size` pixels per side and
+    /// returns them in this order. Tiles at the right and bottom border are
+    /// cut to the image size.
+    pub fn tiles(&self, image_width: i32, image_height: i32, tile_size: i32) -> Vec<Tile> {
+        let tile_size = tile_size.max(1);
+        let columns = (image_width + tile_size - 1) / tile_size;
+        let rows = (image_height + tile_size - 1) / tile_size;
+
+        let mut cells: Vec<(i32, i32)> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect();
+
+        match self {
+            TileOrder::Scanline => {}
+            TileOrder::Spiral => {
+                let center_x = (columns - 1) as f64 / 2.0;
+                let center_y = (rows - 1) as f64 / 2.0;
+                let key = |&(column, row): &(i32, i32)| {
+                    let dx = column as f64 - center_x;
+                    let dy = row as f64 - center_y;
+                    // Rings are squares around the center, walked clockwise
+                    // starting from the top.
+                    let ring = dx.abs().max(dy.abs()).round();
+                    let angle = f64::atan2(dx, -dy).rem_euclid(2.0 * PI);
+                    (ring, angle)
+                };
+                cells.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+            }
+            TileOrder::Hilbert => {
+                let size = (columns.max(rows).max(1) as u32).next_power_of_two();
+                cells.sort_by_key(|&(column, row)| hilbert_index(size, column as u32, row as u32));
+            }
+        }
+
+        cells
+            .into_iter()
+            .map(|(column, row)| {
+                let x = column * tile_size;
+                let y = row * tile_size;
+                Tile {
+                    x,
+                    y,
+                    width: tile_size.min(image_width - x),
+                    height: tile_size.min(image_height - y),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the distance along the Hilbert curve that fills a `size` by
+/// `size` grid, `size` being a power of two, of the cell `(x, y)`.
+fn hilbert_index(size: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+
+        // Rotate the quadrant so that the curve inside it has the right
+        // orientation.
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    #[test]
+    fn every_order_covers_each_pixel_once() {
+        for (width, height, tile_size) in [(37, 23, 8), (64, 64, 16), (5, 90, 7), (1, 1, 8)] {
+            for order in ORDERS {
+                let mut coverage = vec![0; (width * height) as usize];
+                for tile in order.tiles(width, height, tile_size) {
+                    assert!(tile.width > 0 && tile.width <= tile_size);
+                    assert!(tile.height > 0 && tile.height <= tile_size);
+                    assert_eq!(tile.x % tile_size, 0);
+                    assert_eq!(tile.y % tile_size, 0);
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            coverage[(y * width + x) as usize] += 1;
+                        }
+                    }
+                }
+                assert!(
+                    coverage.iter().all(|count| *count == 1),
+                    "{:?} of {}x{} with {} pixel tiles",
+                    order,
+                    width,
+                    height,
+                    tile_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn consecutive_hilbert_tiles_are_adjacent() {
+        for tiles_per_side in [1, 2, 4, 8, 16] {
+            let size = tiles_per_side * 4;
+            let tiles = TileOrder::Hilbert.tiles(size, size, 4);
+            assert_eq!(tiles.len(), (tiles_per_side * tiles_per_side) as usize);
+            for pair in tiles.windows(2) {
+                let distance = (pair[0].x - pair[1].x).abs() + (pair[0].y - pair[1].y).abs();
+                assert_eq!(distance, 4, "{:?} and {:?}", pair[0], pair[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn crop_regions_are_clamped_to_the_frame() {
+        let region = |window: CropWindow| {
+            let tile = window.region(10, 8);
+            (tile.x, tile.y, tile.width, tile.height)
+        };
+        let pixels = |x, y, width, height| CropWindow::Pixels {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        assert_eq!(region(pixels(2, 3, 4, 2)), (2, 3, 4, 2));
+        assert_eq!(region(pixels(-5, -3, 20, 10)), (0, 0, 10, 7));
+        assert_eq!(region(pixels(7, 6, 20, 20)), (7, 6, 3, 2));
+        assert_eq!(region(pixels(12, 2, 4, 4)).2, 0);
+        assert_eq!(region(pixels(2, -9, 4, 4)).3, 0);
+        assert_eq!(region(pixels(5, 5, -3, 2)).2, 0);
+
+        let normalized = CropWindow::Normalized {
+            x_min: -0.5,
+            y_min: 0.5,
+            x_max: 1.5,
+            y_max: 2.0,
+        };
+        assert_eq!(region(normalized), (0, 4, 10, 4));
+        let outside = CropWindow::Normalized {
+            x_min: 1.1,
+            y_min: 0.0,
+            x_max: 1.5,
+            y_max: 1.0,
+        };
+        assert_eq!(region(outside).2, 0);
+    }
+}
@@ -0,0 +1,141 @@
+use super::{utility::Point, vec3::Vec3};
+
+type Matrix = [[f64; 4]; 3];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+];
+
+/// Affine transformation stored as a 3x4 matrix together with its inverse,
+/// so that neither has to be computed while tracing.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+#[allow(dead_code)]
+impl Transform {
+    pub const IDENTITY: Transform = Self {
+        m: IDENTITY,
+        inv: IDENTITY,
+    };
+
+    pub fn new() -> Self {
+        Self::IDENTITY
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let (x, y, z) = (offset.x(), offset.y(), offset.z());
+        Self {
+            m: [[1.0, 0.0, 0.0, x], [0.0, 1.0, 0.0, y], [0.0, 0.0, 1.0, z]],
+            inv: [
+                [1.0, 0.0, 0.0, -x],
+                [0.0, 1.0, 0.0, -y],
+                [0.0, 0.0, 1.0, -z],
+            ],
+        }
+    }
+
+    pub fn scale(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            m: [[x, 0.0, 0.0, 0.0], [0.0, y, 0.0, 0.0], [0.0, 0.0, z, 0.0]],
+            inv: [
+                [1.0 / x, 0.0, 0.0, 0.0],
+                [0.0, 1.0 / y, 0.0, 0.0],
+                [0.0, 0.0, 1.0 / z, 0.0],
+            ],
+        }
+    }
+
+    /// Rotation around the x axis by `angle` degrees.
+    pub fn rotation_x(angle: f64) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::from_rotation([[1.0, 0.0, 0.0], [0.0, cos, -sin], [0.0, sin, cos]])
+    }
+
+    /// Rotation around the y axis by `angle` degrees.
+    pub fn rotation_y(angle: f64) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::from_rotation([[cos, 0.0, sin], [0.0, 1.0, 0.0], [-sin, 0.0, cos]])
+    }
+
+    /// Rotation around the z axis by `angle` degrees.
+    pub fn rotation_z(angle: f64) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::from_rotation([[cos, -sin, 0.0], [sin, cos, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    /// Returns the transformation that applies `self` first and `next`
+    /// afterwards.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            m: multiply(&next.m, &self.m),
+            inv: multiply(&self.inv, &next.inv),
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(&self, p: &Point) -> Point {
+        apply(&self.m, p, 1.0)
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        apply(&self.m, v, 0.0)
+    }
+
+    /// Transforms a surface normal, which requires the inverse transpose.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        let inv = &self.inv;
+        Vec3::from(
+            inv[0][0] * n.x() + inv[1][0] * n.y() + inv[2][0] * n.z(),
+            inv[0][1] * n.x() + inv[1][1] * n.y() + inv[2][1] * n.z(),
+            inv[0][2] * n.x() + inv[1][2] * n.y() + inv[2][2] * n.z(),
+        )
+    }
+
+    fn from_rotation(r: [[f64; 3]; 3]) -> Self {
+        // The inverse of a rotation is its transpose.
+        Self {
+            m: [
+                [r[0][0], r[0][1], r[0][2], 0.0],
+                [r[1][0], r[1][1], r[1][2], 0.0],
+                [r[2][0], r[2][1], r[2][2], 0.0],
+            ],
+            inv: [
+                [r[0][0], r[1][0], r[2][0], 0.0],
+                [r[0][1], r[1][1], r[2][1], 0.0],
+                [r[0][2], r[1][2], r[2][2], 0.0],
+            ],
+        }
+    }
+}
+
+fn apply(m: &Matrix, v: &Vec3, w: f64) -> Vec3 {
+    Vec3::from(
+        m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z() + m[0][3] * w,
+        m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z() + m[1][3] * w,
+        m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z() + m[2][3] * w,
+    )
+}
+
+/// Multiplies two affine matrices, treating the implicit last row as
+/// `[0, 0, 0, 1]`.
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 3];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = a[i][0] * b[0][j] + a[i][1] * b[1][j] + a[i][2] * b[2][j];
+        }
+        row[3] += a[i][3];
+    }
+    result
+}
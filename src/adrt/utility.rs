@@ -1,10 +1,8 @@
 use crate::adrt::vec3::Vec3;
 
-use rand::Rng;
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::Write;
 
-use super::interval::Interval;
+use super::{interval::Interval, rng::Pcg32};
 
 pub type Color = Vec3;
 pub type Point = Vec3;
@@ -13,29 +11,22 @@ pub fn linear_to_gamma(linear_component: f64) -> f64 {
     f64::sqrt(linear_component)
 }
 
-pub fn write_color(
-    file: &mut BufWriter<File>,
-    pixel_color: Color,
-    samples_per_pixel: i32,
-) -> std::io::Result<()> {
-    let scale = 1.0 / samples_per_pixel as f64;
+pub fn write_color(writer: &mut impl Write, pixel_color: Color) -> std::io::Result<()> {
     let intensity = Interval::from(0.000, 0.999);
-    let mut pixels = Vec::<u8>::with_capacity(3);
-    pixels.push((intensity.clamp(linear_to_gamma(pixel_color.x() * scale)) * 255.0) as u8);
-    pixels.push((intensity.clamp(linear_to_gamma(pixel_color.y() * scale)) * 255.0) as u8);
-    pixels.push((intensity.clamp(linear_to_gamma(pixel_color.z() * scale)) * 255.0) as u8);
+    let pixels = [
+        (intensity.clamp(linear_to_gamma(pixel_color.x())) * 255.0) as u8,
+        (intensity.clamp(linear_to_gamma(pixel_color.y())) * 255.0) as u8,
+        (intensity.clamp(linear_to_gamma(pixel_color.z())) * 255.0) as u8,
+    ];
 
-    file.write(&pixels)?;
+    writer.write_all(&pixels)?;
     Ok(())
 }
 
-pub fn random() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(0.0..1.0)
+pub fn random(rng: &mut Pcg32) -> f64 {
+    rng.next_f64()
 }
 
-#[allow(dead_code)]
-pub fn random_range(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min..max)
+pub fn random_range(rng: &mut Pcg32, min: f64, max: f64) -> f64 {
+    min + (max - min) * rng.next_f64()
 }
@@ -1,6 +1,9 @@
-use std::ops;
+use std::{f64::consts::PI, ops};
 
-use super::utility::{random, random_range};
+use super::{
+    rng::Pcg32,
+    utility::{random, random_range},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Vec3 {
@@ -28,9 +31,9 @@ pub fn unit_vector(v: Vec3) -> Vec3 {
 }
 
 #[allow(dead_code)]
-pub fn random_in_unit_sphere() -> Vec3 {
+pub fn random_in_unit_sphere(rng: &mut Pcg32) -> Vec3 {
     loop {
-        let vec = Vec3::from_random_range(-1.0, 1.0);
+        let vec = Vec3::from_random_range(rng, -1.0, 1.0);
         if vec.length_squared() < 1.0 {
             return vec;
         }
@@ -38,13 +41,13 @@ pub fn random_in_unit_sphere() -> Vec3 {
 }
 
 #[allow(dead_code)]
-pub fn random_unit_vector() -> Vec3 {
-    unit_vector(random_in_unit_sphere())
+pub fn random_unit_vector(rng: &mut Pcg32) -> Vec3 {
+    unit_vector(random_in_unit_sphere(rng))
 }
 
 #[allow(dead_code)]
-pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
-    let on_unit_sphere = random_unit_vector();
+pub fn random_on_hemisphere(normal: &Vec3, rng: &mut Pcg32) -> Vec3 {
+    let on_unit_sphere = random_unit_vector(rng);
     if dot_product(normal, &on_unit_sphere) > 0.0 {
         on_unit_sphere
     } else {
@@ -52,6 +55,32 @@ pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
     }
 }
 
+/// Maps a point of `[0, 1)^2` to the unit disk in the xy plane. The
+/// concentric mapping of Shirley and Chiu keeps neighbouring points close,
+/// so stratified samples stay stratified.
+pub fn sample_unit_disk((u0, u1): (f64, f64)) -> Vec3 {
+    let a = 2.0 * u0 - 1.0;
+    let b = 2.0 * u1 - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return Vec3::new();
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::from(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+/// Maps a point of `[0, 1)^2` uniformly to the surface of the unit sphere.
+pub fn sample_unit_sphere((u0, u1): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u0;
+    let r = f64::sqrt((1.0 - z * z).max(0.0));
+    let phi = 2.0 * PI * u1;
+    Vec3::from(r * phi.cos(), r * phi.sin(), z)
+}
+
 #[allow(dead_code)]
 pub fn reflect(v: &Vec3, normal: &Vec3) -> Vec3 {
     v - 2.0 * dot_product(v, normal) * normal
@@ -70,15 +99,15 @@ impl Vec3 {
         Self { e0, e1, e2 }
     }
 
-    pub fn from_random() -> Vec3 {
-        Vec3::from(random(), random(), random())
+    pub fn from_random(rng: &mut Pcg32) -> Vec3 {
+        Vec3::from(random(rng), random(rng), random(rng))
     }
 
-    pub fn from_random_range(min: f64, max: f64) -> Vec3 {
+    pub fn from_random_range(rng: &mut Pcg32, min: f64, max: f64) -> Vec3 {
         Vec3::from(
-            random_range(min, max),
-            random_range(min, max),
-            random_range(min, max),
+            random_range(rng, min, max),
+            random_range(rng, min, max),
+            random_range(rng, min, max),
         )
     }
 
@@ -130,9 +159,9 @@ impl ops::Neg for Vec3 {
 
     fn neg(self) -> Self::Output {
         Self::Output {
-            e0: self.e0 * (-1 as f64),
-            e1: self.e1 * (-1 as f64),
-            e2: self.e2 * (-1 as f64),
+            e0: -self.e0,
+            e1: -self.e1,
+            e2: -self.e2,
         }
     }
 }
@@ -157,11 +186,11 @@ impl<'a> ops::Add<&'a Vec3> for &Vec3 {
     }
 }
 
-impl<'a> ops::Add<Vec3> for &Vec3 {
+impl ops::Add<Vec3> for &Vec3 {
     type Output = Vec3;
 
     fn add(self, rhs: Vec3) -> Self::Output {
-        *self + &rhs
+        *self + rhs
     }
 }
 
@@ -201,11 +230,11 @@ impl<'a> ops::Sub<&'a Vec3> for &Vec3 {
     }
 }
 
-impl<'a> ops::Sub<Vec3> for &Vec3 {
+impl ops::Sub<Vec3> for &Vec3 {
     type Output = Vec3;
 
     fn sub(self, rhs: Vec3) -> Self::Output {
-        *self - &rhs
+        *self - rhs
     }
 }
 
@@ -0,0 +1,272 @@
+use std::{f64::consts::PI, sync::Arc, time::Instant};
+
+use crate::adrt::{
+    bvh::Bvh,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    instance::{Instance, Tlas},
+    interval::Interval,
+    material::MaterialType,
+    ray::Ray,
+    rng::Pcg32,
+    sampler::{Sampler, SamplerType},
+    sphere::Sphere,
+    transform::Transform,
+    utility::{random, random_range, Color, Point},
+    vec3::Vec3,
+};
+
+/// Compares the BVH against a plain `HittableList` on a scene of randomly
+/// placed spheres. Every ray has to produce the same closest hit with both,
+/// otherwise the images would differ.
+pub fn bvh(object_count: usize, ray_count: usize) {
+    let mut rng = Pcg32::new(0, 0);
+    let material = MaterialType::LAMBERTIAN(Color::from(0.5, 0.5, 0.5));
+    let spheres: Vec<(Point, f64)> = (0..object_count)
+        .map(|_| {
+            (
+                Point::from(
+                    random_range(&mut rng, -50.0, 50.0),
+                    random_range(&mut rng, -50.0, 50.0),
+                    random_range(&mut rng, -150.0, -50.0),
+                ),
+                random_range(&mut rng, 0.2, 1.0),
+            )
+        })
+        .collect();
+
+    let mut list = HittableList::new();
+    let mut bvh_list = HittableList::new();
+    for (center, radius) in spheres.iter() {
+        list.add(Box::new(Sphere::from(*center, *radius, material)));
+        bvh_list.add(Box::new(Sphere::from(*center, *radius, material)));
+    }
+
+    let start = Instant::now();
+    let bvh = Bvh::from(bvh_list);
+    let build_time = start.elapsed();
+
+    let rays: Vec<Ray> = (0..ray_count)
+        .map(|_| {
+            let direction = Vec3::from(random(&mut rng) - 0.5, random(&mut rng) - 0.5, -1.0);
+            Ray::from(Point::new(), direction)
+        })
+        .collect();
+
+    let (list_time, list_hits) = trace(&list, &rays);
+    let (bvh_time, bvh_hits) = trace(&bvh, &rays);
+
+    let mismatches = list_hits
+        .iter()
+        .zip(bvh_hits.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+
+    let start = Instant::now();
+    let occluded: Vec<bool> = rays
+        .iter()
+        .map(|ray| bvh.occluded(ray, &Interval::from(0.001, f64::INFINITY)))
+        .collect();
+    let occluded_time = start.elapsed();
+    let occlusion_mismatches = occluded
+        .iter()
+        .zip(bvh_hits.iter())
+        .filter(|(occluded, hit)| **occluded != hit.is_some())
+        .count();
+
+    println!("objects:       {}", object_count);
+    println!("rays:          {}", ray_count);
+    println!("bvh build:     {:.3?}", build_time);
+    println!("list:          {:.3?}", list_time);
+    println!("bvh:           {:.3?}", bvh_time);
+    println!(
+        "speedup:       {:.1}x",
+        list_time.as_secs_f64() / bvh_time.as_secs_f64()
+    );
+    println!("bvh occluded:  {:.3?}", occluded_time);
+    println!("mismatches:    {}", mismatches + occlusion_mismatches);
+}
+
+/// Places `instance_count` copies of one small cluster of spheres in a two
+/// level hierarchy, moves all of them and compares refitting the top level
+/// against rebuilding it. The hits are checked against a flat BVH that holds
+/// a separate copy of every sphere.
+pub fn instancing(instance_count: usize, ray_count: usize) {
+    let mut rng = Pcg32::new(0, 0);
+    let material = MaterialType::LAMBERTIAN(Color::from(0.5, 0.5, 0.5));
+    let cluster: Vec<(Point, f64)> = (0..100)
+        .map(|_| {
+            (
+                Point::from_random_range(&mut rng, -1.0, 1.0),
+                random_range(&mut rng, 0.05, 0.2),
+            )
+        })
+        .collect();
+    let offsets: Vec<Vec3> = (0..instance_count)
+        .map(|_| {
+            Vec3::from(
+                random_range(&mut rng, -50.0, 50.0),
+                random_range(&mut rng, -50.0, 50.0),
+                random_range(&mut rng, -150.0, -50.0),
+            )
+        })
+        .collect();
+
+    let mut blas_list = HittableList::new();
+    for (center, radius) in cluster.iter() {
+        blas_list.add(Box::new(Sphere::from(*center, *radius, material)));
+    }
+    let blas: Arc<dyn Hittable> = Arc::new(Bvh::from(blas_list));
+
+    let instances = offsets
+        .iter()
+        .map(|offset| Instance::from(blas.clone(), Transform::translation(*offset)))
+        .collect();
+    let mut tlas = Tlas::from_objects(instances);
+
+    let rays: Vec<Ray> = (0..ray_count)
+        .map(|_| {
+            let direction = Vec3::from(random(&mut rng) - 0.5, random(&mut rng) - 0.5, -1.0);
+            Ray::from(Point::new(), direction)
+        })
+        .collect();
+
+    // Move every instance a little and update the top level.
+    let shift = Vec3::from(0.5, 0.25, 0.0);
+    let start = Instant::now();
+    for (i, offset) in offsets.iter().enumerate() {
+        tlas.object_mut(i)
+            .set_transform(Transform::translation(*offset + shift));
+    }
+    tlas.refit();
+    let refit_time = start.elapsed();
+
+    let start = Instant::now();
+    let rebuilt = Tlas::from_objects(
+        offsets
+            .iter()
+            .map(|offset| Instance::from(blas.clone(), Transform::translation(*offset + shift)))
+            .collect(),
+    );
+    let rebuild_time = start.elapsed();
+
+    let mut flat_list = HittableList::new();
+    for offset in offsets.iter() {
+        for (center, radius) in cluster.iter() {
+            flat_list.add(Box::new(Sphere::from(
+                *center + *offset + shift,
+                *radius,
+                material,
+            )));
+        }
+    }
+    let flat = Bvh::from(flat_list);
+
+    let (refit_trace_time, refit_hits) = trace(&tlas, &rays);
+    let (rebuilt_trace_time, rebuilt_hits) = trace(&rebuilt, &rays);
+    let (flat_trace_time, flat_hits) = trace(&flat, &rays);
+
+    let mismatches = |hits: &Vec<Option<f64>>| {
+        hits.iter()
+            .zip(flat_hits.iter())
+            .filter(|(a, b)| match (a, b) {
+                (Some(a), Some(b)) => (a - b).abs() > 1e-9,
+                _ => a != b,
+            })
+            .count()
+    };
+
+    println!("instances:     {}", instance_count);
+    println!("spheres:       {}", instance_count * cluster.len());
+    println!("refit:         {:.3?}", refit_time);
+    println!("rebuild:       {:.3?}", rebuild_time);
+    println!("refit trace:   {:.3?}", refit_trace_time);
+    println!("rebuilt trace: {:.3?}", rebuilt_trace_time);
+    println!("flat trace:    {:.3?}", flat_trace_time);
+    println!(
+        "mismatches:    {} refit, {} rebuilt",
+        mismatches(&refit_hits),
+        mismatches(&rebuilt_hits)
+    );
+}
+
+/// Estimates two integrals with known values in every pixel of a
+/// `pixel_count` wide row and reports the RMS error of each sampler at
+/// equal sample counts. The first integrand is the quarter disk, a hard edge
+/// like the silhouette of an object in a pixel, the second one is smooth
+/// and uses four dimensions, like a pixel and a lens sample.
+pub fn samplers(pixel_count: i32, sample_counts: &[u32]) {
+    let integrands: [(&str, Integrand, f64); 2] = [
+        ("disk", quarter_disk, PI / 4.0),
+        ("smooth 4d", smooth_4d, 4.0 / (PI * PI)),
+    ];
+    let sampler_types = [
+        ("independent", SamplerType::Independent),
+        ("stratified", SamplerType::Stratified),
+        ("halton", SamplerType::Halton),
+        ("sobol", SamplerType::Sobol),
+    ];
+
+    println!("pixels:        {}", pixel_count);
+    for (name, integrand, expected) in integrands.iter() {
+        println!();
+        print!("{:<15}", name);
+        for samples in sample_counts {
+            print!("{:>12}", format!("{} spp", samples));
+        }
+        println!();
+
+        for (sampler_name, sampler_type) in sampler_types.iter() {
+            print!("{:<15}", sampler_name);
+            for &samples in sample_counts {
+                let mut sampler = sampler_type.create(samples, 0);
+                let mut squared_error = 0.0;
+                for i in 0..pixel_count {
+                    let mut sum = 0.0;
+                    for sample in 0..samples {
+                        sampler.start_pixel_sample((i, 0), sample);
+                        sum += integrand(sampler.as_mut());
+                    }
+                    let error = sum / samples as f64 - expected;
+                    squared_error += error * error;
+                }
+                print!("{:>12.3e}", f64::sqrt(squared_error / pixel_count as f64));
+            }
+            println!();
+        }
+    }
+}
+
+type Integrand = fn(&mut dyn Sampler) -> f64;
+
+fn quarter_disk(sampler: &mut dyn Sampler) -> f64 {
+    let (x, y) = sampler.get_2d();
+    if x * x + y * y < 1.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn smooth_4d(sampler: &mut dyn Sampler) -> f64 {
+    let (x, y) = sampler.get_2d();
+    let (z, w) = sampler.get_2d();
+    f64::sin(PI * x) * f64::sin(PI * y) * (z + w)
+}
+
+fn trace(world: &impl Hittable, rays: &[Ray]) -> (std::time::Duration, Vec<Option<f64>>) {
+    let start = Instant::now();
+    let hits = rays
+        .iter()
+        .map(|ray| {
+            let mut record = HitRecord::new();
+            if world.hit(ray, &Interval::from(0.001, f64::INFINITY), &mut record) {
+                Some(record.t)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    (start.elapsed(), hits)
+}
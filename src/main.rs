@@ -1,10 +1,39 @@
 mod adrt;
+mod bench;
 
-use crate::adrt::{hittable_list::HittableList, sphere::Sphere, utility::Point};
+use crate::adrt::{bvh::Bvh, hittable_list::HittableList, sphere::Sphere, utility::Point};
 
-use adrt::{camera::Camera, material::MaterialType, utility::Color};
+use adrt::{
+    camera::Camera,
+    error::RenderError,
+    material::MaterialType,
+    progress::{JsonLinesObserver, QuietObserver},
+    stream,
+    utility::Color,
+    vec3::Vec3,
+};
+use std::{process::ExitCode, sync::Arc};
 
-fn main() -> std::io::Result<()> {
+fn main() -> ExitCode {
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        bench::bvh(10_000, 50_000);
+        println!();
+        bench::instancing(10_000, 50_000);
+        println!();
+        bench::samplers(4096, &[4, 16, 64, 256]);
+        return ExitCode::SUCCESS;
+    }
+
+    match render() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn render() -> Result<(), RenderError> {
     let file_path = "image.ppm";
 
     let material_ground = MaterialType::LAMBERTIAN(Color::from(0.8, 0.8, 0.0));
@@ -34,6 +63,8 @@ fn main() -> std::io::Result<()> {
         material_right,
     )));
 
+    let world = Bvh::from(world);
+
     let mut camera = Camera::new();
 
     camera.aspect_ratio = 16.0 / 9.0;
@@ -41,7 +72,25 @@ fn main() -> std::io::Result<()> {
     camera.samples_per_pixel = 50;
     camera.max_depth = 10;
 
-    camera.render(&file_path.to_string(), &world);
+    camera.vfov = 90.0;
+    camera.lookfrom = Point::from(0.0, 0.0, 0.0);
+    camera.lookat = Point::from(0.0, 0.0, -1.0);
+    camera.vup = Vec3::from(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 1.0;
+
+    if std::env::args().any(|arg| arg == "--quiet") {
+        camera.observer = Arc::new(QuietObserver);
+    } else if std::env::args().any(|arg| arg == "--json-progress") {
+        camera.observer = Arc::new(JsonLinesObserver::new(std::io::stdout()));
+    }
+
+    if std::env::args().any(|arg| arg == "--stream") {
+        let mut writer = stream::create(file_path)?;
+        return camera.render_streamed(&world, writer.as_mut());
+    }
 
-    Ok(())
+    let image = camera.render(&world)?;
+    image.save(file_path)
 }